@@ -0,0 +1,75 @@
+//! Synchronous versions of the async entry points, for programs that don't
+//! run a Tokio runtime themselves.
+//!
+//! Every call runs on one runtime shared by the whole process, created on
+//! first use. Like `reqwest::blocking`, these functions panic when called
+//! from within an async context.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use tokio::runtime::{Builder, Runtime};
+#[cfg(feature = "remote")]
+use url::Url;
+
+use crate::deck::{CardImages, Deck, DeckGenerationOptions};
+use crate::error::{Failure, Result};
+use crate::printing::PrintingPolicy;
+use crate::progress::Reporter;
+#[cfg(feature = "remote")]
+use crate::remote::SourcesConfig;
+
+/// The Scryfall client holds on to connections of the runtime it was first
+/// used on, so every blocking call has to go through the same one.
+fn runtime() -> Result<&'static Runtime> {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+
+    let runtime = Builder::new_multi_thread().enable_all().build()?;
+    // Another thread may have won the race, either runtime will do
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Blocking version of `local::get_local_deck`.
+pub fn get_local_deck(path: PathBuf, options: &DeckGenerationOptions) -> Result<Deck> {
+    runtime()?.block_on(crate::local::get_local_deck(path, options))
+}
+
+/// Blocking version of `remote::get_remote_deck`.
+#[cfg(feature = "remote")]
+pub fn get_remote_deck(url: Url, sources: &SourcesConfig, reporter: &Reporter) -> Result<Deck> {
+    runtime()?.block_on(crate::remote::get_remote_deck(url, sources, reporter))
+}
+
+/// Blocking version of `Deck::apply_printing_policy`.
+pub fn apply_printing_policy(
+    deck: &mut Deck,
+    policy: &PrintingPolicy,
+    tolerant: bool,
+    reporter: &Reporter,
+) -> Result<()> {
+    runtime()?.block_on(deck.apply_printing_policy(policy, tolerant, reporter))
+}
+
+/// Blocking version of `Deck::localize`.
+pub fn localize(deck: &mut Deck, lang: &str, tolerant: bool, reporter: &Reporter) -> Result<()> {
+    runtime()?.block_on(deck.localize(lang, tolerant, reporter))
+}
+
+/// Blocking version of `Deck::download`.
+pub fn download(deck: &Deck, options: DeckGenerationOptions) -> Result<Vec<Failure>> {
+    runtime()?.block_on(deck.download(options))
+}
+
+/// Blocking version of `Deck::fetch_images`.
+pub fn fetch_images(deck: &Deck, options: DeckGenerationOptions) -> Result<CardImages> {
+    runtime()?.block_on(deck.fetch_images(options))
+}
+
+/// Blocking version of `Deck::generate`.
+#[cfg(feature = "pdf")]
+pub fn generate(deck: &Deck, options: DeckGenerationOptions) -> Result<Vec<Failure>> {
+    runtime()?.block_on(deck.generate(options))
+}
@@ -0,0 +1,274 @@
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use image::codecs::jpeg::JpegDecoder;
+use image::{ImageDecoder, ImageFormat};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::deck::{Card, ImageVersion};
+use crate::error::{Error, Result};
+use crate::progress::{Progress, Reporter};
+
+/// Anything smaller than this is an error page or a cut off download rather than a card scan.
+const MIN_IMAGE_SIZE: usize = 1024;
+
+/// A cached card image.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// When the image was last downloaded or used for a print.
+    pub last_used: SystemTime,
+}
+
+/// What is known about where a cached image came from, stored next to it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImageMeta {
+    /// Scryfall's `image_status`, e.g. "lowres" or "highres_scan".
+    pub image_status: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    pub count: usize,
+    pub size: u64,
+    pub oldest: Option<SystemTime>,
+}
+
+/// Where card images are cached.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Writable cache, `$XDG_CACHE_HOME/tiffer/cards` if not set.
+    pub dir: Option<String>,
+    /// Read-only cache checked before downloading, e.g. a copy shared on a team NAS.
+    pub shared_dir: Option<String>,
+}
+
+impl CacheConfig {
+    /// Directory downloaded images are written to.
+    pub fn dir(&self) -> Result<String> {
+        if let Some(dir) = &self.dir {
+            return Ok(dir.clone());
+        }
+
+        let cache_home = match std::env::var("XDG_CACHE_HOME") {
+            Ok(cache_home) => cache_home,
+            Err(_) => match std::env::var("HOME") {
+                Ok(home) => format!("{}/.cache", home),
+                Err(_) => return Err(Error::NoCacheDir),
+            },
+        };
+
+        Ok(format!("{}/tiffer/cards", cache_home))
+    }
+
+    /// The cache for one of Scryfall's image versions. Large images live at
+    /// the top level, other versions in a subdirectory named after them.
+    pub fn for_version(&self, version: ImageVersion) -> CacheConfig {
+        let subdir = |dir: &String| match version {
+            ImageVersion::Large => dir.clone(),
+            version => format!("{}/{}", dir, version.as_str()),
+        };
+
+        CacheConfig {
+            dir: self.dir.as_ref().map(subdir).or_else(|| {
+                // Keep the default location when no directory is configured
+                self.dir().ok().map(|dir| subdir(&dir))
+            }),
+            shared_dir: self.shared_dir.as_ref().map(subdir),
+        }
+    }
+
+    /// Finds an intact cached image, looking in the writable cache before the shared one.
+    pub fn find(&self, card: &Card) -> Result<Option<String>> {
+        let dirs = std::iter::once(self.dir()?).chain(self.shared_dir.clone());
+
+        for dir in dirs {
+            let path = card.cached_path(dir);
+            if Path::new(&path).exists() && is_valid_cached(&path) {
+                return Ok(Some(path));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Lists every cached image, least recently used first.
+pub fn entries(dir: &str) -> Result<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+    if Path::new(dir).is_dir() {
+        collect_entries(Path::new(dir), &mut entries)?;
+    }
+
+    entries.sort_by_key(|entry| entry.last_used);
+    Ok(entries)
+}
+
+// Images of other languages live in subdirectories
+fn collect_entries(dir: &Path, entries: &mut Vec<CacheEntry>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let path = entry.path();
+
+        if metadata.is_dir() {
+            collect_entries(&path, entries)?;
+        } else if path.extension().is_some_and(|ext| ext == "jpg") {
+            entries.push(CacheEntry {
+                path,
+                size: metadata.len(),
+                last_used: metadata.modified()?,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+pub fn stats(dir: &str) -> Result<CacheStats> {
+    let entries = entries(dir)?;
+
+    Ok(CacheStats {
+        count: entries.len(),
+        size: entries.iter().map(|entry| entry.size).sum(),
+        oldest: entries.first().map(|entry| entry.last_used),
+    })
+}
+
+/// Removes images that haven't been used for longer than `older_than`.
+pub fn clean(dir: &str, older_than: Duration) -> Result<Vec<CacheEntry>> {
+    let cutoff = SystemTime::now() - older_than;
+
+    remove(
+        entries(dir)?
+            .into_iter()
+            .filter(|entry| entry.last_used < cutoff)
+            .collect(),
+    )
+}
+
+/// Decodes every cached image and removes the ones that are broken.
+pub fn verify(dir: &str, reporter: &Reporter) -> Result<Vec<CacheEntry>> {
+    let entries = entries(dir)?;
+    let progress = Progress::new(reporter, "verify", entries.len() as u64);
+
+    let mut corrupt = Vec::new();
+    for entry in entries {
+        let item = entry.path.display().to_string();
+        let decodes = fs::read(&entry.path).is_ok_and(|data| {
+            is_valid_image(&data)
+                && image::load_from_memory_with_format(&data, ImageFormat::Jpeg).is_ok()
+        });
+        if !decodes {
+            corrupt.push(entry);
+        }
+        progress.inc(&item);
+    }
+    progress.finish();
+
+    remove(corrupt)
+}
+
+/// Evicts the least recently used images until the cache fits in `max_size` bytes.
+pub fn prune(dir: &str, max_size: u64) -> Result<Vec<CacheEntry>> {
+    let entries = entries(dir)?;
+    let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+
+    let mut evicted = Vec::new();
+    for entry in entries {
+        if size <= max_size {
+            break;
+        }
+        size -= entry.size;
+        evicted.push(entry);
+    }
+
+    remove(evicted)
+}
+
+fn remove(entries: Vec<CacheEntry>) -> Result<Vec<CacheEntry>> {
+    for entry in &entries {
+        fs::remove_file(&entry.path)?;
+
+        let meta_path = entry.path.with_extension("json");
+        if meta_path.exists() {
+            fs::remove_file(meta_path)?;
+        }
+    }
+
+    Ok(entries)
+}
+
+fn meta_path(image_path: &str) -> String {
+    format!("{}.json", image_path.trim_end_matches(".jpg"))
+}
+
+/// Reads the metadata stored alongside a cached image, if there is any.
+pub fn read_meta(image_path: &str) -> Option<ImageMeta> {
+    let data = fs::read_to_string(meta_path(image_path)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+pub fn write_meta(image_path: &str, meta: &ImageMeta) -> Result<()> {
+    write_atomic(
+        &meta_path(image_path),
+        serde_json::to_string(meta)
+            .map_err(|e| Error::Io(std::io::Error::other(e)))?
+            .as_bytes(),
+    )
+}
+
+/// Marks a cached image as used, so that pruning evicts it last.
+pub fn touch(path: &str) {
+    // The cache may be read-only, which only makes eviction less accurate
+    let _ = fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+}
+
+/// Checks that an image is a complete JPEG with a readable header.
+pub fn is_valid_image(data: &[u8]) -> bool {
+    if data.len() < MIN_IMAGE_SIZE || !data.ends_with(&[0xFF, 0xD9]) {
+        return false;
+    }
+
+    match JpegDecoder::new(Cursor::new(data)) {
+        Ok(decoder) => {
+            let (width, height) = decoder.dimensions();
+            width > 0 && height > 0
+        }
+        Err(_) => false,
+    }
+}
+
+/// Checks that a cached image exists and is intact.
+pub fn is_valid_cached(path: &str) -> bool {
+    fs::read(path).is_ok_and(|data| is_valid_image(&data))
+}
+
+/// Writes a file so that it either appears complete or not at all, even if
+/// tiffer is interrupted halfway through.
+pub fn write_atomic(path: &str, data: &[u8]) -> Result<()> {
+    let temp_path = format!("{}.{:08x}.tmp", path, rand::thread_rng().gen::<u32>());
+
+    let result = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() && Path::new(&temp_path).exists() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    Ok(result?)
+}
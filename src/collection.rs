@@ -0,0 +1,414 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+#[cfg(feature = "csv")]
+use std::path::Path;
+use std::path::PathBuf;
+
+use tokio::task;
+
+use crate::deck::{Card, Deck};
+use crate::error::{Error, Result};
+use crate::local::{get_card_details, section_header};
+use crate::progress::{Progress, Reporter};
+
+/// How cards in the deck are matched against the owned collection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OwnedMatch {
+    /// Any printing of a card counts as owned.
+    #[default]
+    Name,
+    /// Only the exact same printing counts as owned.
+    Printing,
+}
+
+#[derive(Debug)]
+pub struct Collection {
+    by: OwnedMatch,
+    owned: HashMap<String, usize>,
+}
+
+#[derive(Debug)]
+struct OwnedCard {
+    quantity: usize,
+    name: String,
+    set: Option<String>,
+    collector_number: Option<String>,
+    scryfall_id: Option<String>,
+}
+
+/// Loads an owned collection from either a CSV export or a plain decklist,
+/// keeping only the cards `deck` is made of.
+pub async fn get_collection(
+    path: PathBuf,
+    by: OwnedMatch,
+    deck: &Deck,
+    reporter: &Reporter,
+) -> Result<Collection> {
+    let entries = match path.extension().and_then(|ext| ext.to_str()) {
+        #[cfg(feature = "csv")]
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => parse_csv(&path)?,
+        #[cfg(not(feature = "csv"))]
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => {
+            return Err(Error::parse(
+                path.display(),
+                "reading CSV needs the csv feature",
+            ))
+        }
+        _ => {
+            let file = File::open(&path).map_err(|e| Error::read(path.display(), e))?;
+            parse_list(BufReader::new(file)).map_err(|e| match e {
+                Error::Io(e) => Error::read(path.display(), e),
+                e => e,
+            })?
+        }
+    };
+
+    // Collections are much larger than decks, skip looking up the rest
+    let names: HashSet<String> = deck
+        .cards
+        .iter()
+        .chain(&deck.tokens)
+        .map(|card| name_key(&card.name))
+        .collect();
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter(|entry| names.contains(&name_key(&entry.name)))
+        .collect();
+
+    let progress = Progress::new(reporter, "collection", entries.len() as u64);
+    let tasks: Vec<_> = entries
+        .into_iter()
+        .map(|entry| {
+            let progress = progress.clone();
+            task::spawn(async move {
+                let name = entry.name.clone();
+                let key = owned_key(entry, by).await;
+                progress.inc(&name);
+                key
+            })
+        })
+        .collect();
+
+    let mut owned = HashMap::new();
+    for task in tasks {
+        if let Some((key, quantity)) = task.await? {
+            *owned.entry(key).or_default() += quantity;
+        }
+    }
+    progress.finish();
+
+    Ok(Collection { by, owned })
+}
+
+/// What an entry is matched by, `None` for entries that can't be matched.
+async fn owned_key(entry: OwnedCard, by: OwnedMatch) -> Option<(String, usize)> {
+    let key = match by {
+        OwnedMatch::Name => name_key(&entry.name),
+        OwnedMatch::Printing => match (entry.scryfall_id, entry.set, entry.collector_number) {
+            (Some(id), _, _) => id,
+            (None, Some(set), Some(collector_number)) => {
+                match get_card_details(&entry.name, &set, &collector_number).await {
+                    Ok(card) => card.id,
+                    Err(e) => {
+                        log::warn!("{}, ignoring {} in the collection", e, entry.name);
+                        return None;
+                    }
+                }
+            }
+            _ => {
+                log::warn!("No printing given for {}, ignoring it", entry.name);
+                return None;
+            }
+        },
+    };
+
+    Some((key, entry.quantity))
+}
+
+/// Compares names by their front face so "Delver of Secrets" matches
+/// "Delver of Secrets // Insectile Aberration".
+fn name_key(name: &str) -> String {
+    name.split(" // ")
+        .next()
+        .unwrap_or(name)
+        .trim()
+        .to_lowercase()
+}
+
+#[cfg(feature = "csv")]
+fn parse_csv(path: &Path) -> Result<Vec<OwnedCard>> {
+    let invalid = |message: String| Error::parse(path.display(), message);
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(File::open(path).map_err(|e| Error::read(path.display(), e))?);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| invalid(e.to_string()))?
+        .clone();
+    let column = |names: &[&str]| {
+        headers.iter().position(|header| {
+            names
+                .iter()
+                .any(|name| header.trim().eq_ignore_ascii_case(name))
+        })
+    };
+
+    let quantity = column(&["count", "quantity", "qty", "amount"]);
+    let name = column(&["name", "card name", "card"])
+        .ok_or_else(|| invalid("no name column".to_string()))?;
+    let set = column(&["edition", "set", "set code"]);
+    let collector_number = column(&["collector number", "card number", "number"]);
+    let scryfall_id = column(&["scryfall id", "scryfall_id"]);
+
+    let mut cards = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid(e.to_string()))?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        let Some(card_name) = field(Some(name)) else {
+            continue;
+        };
+
+        cards.push(OwnedCard {
+            quantity: match field(quantity) {
+                Some(quantity) => quantity
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid quantity {}", quantity)))?,
+                None => 1,
+            },
+            name: card_name,
+            set: field(set),
+            collector_number: field(collector_number),
+            scryfall_id: field(scryfall_id),
+        });
+    }
+
+    Ok(cards)
+}
+
+// example line: 1 Whiptongue Hydra (NEC) 134, set and collector number are optional
+fn parse_list(reader: impl BufRead) -> Result<Vec<OwnedCard>> {
+    let mut cards = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        // Every section counts, the collection doesn't care where a card goes
+        if line.is_empty()
+            || line.starts_with("//")
+            || line.starts_with('#')
+            || section_header(line).is_some()
+        {
+            continue;
+        }
+
+        let mut parts: Vec<&str> = line.split_whitespace().collect();
+        if matches!(parts.last(), Some(&"*F*" | &"*E*")) {
+            parts.pop();
+        }
+        if parts.len() < 2 {
+            return Err(Error::parse(line, "expected a quantity and name"));
+        }
+        let quantity = parts[0]
+            .trim_end_matches('x')
+            .parse::<usize>()
+            .map_err(|_| Error::parse(line, "invalid quantity"))?;
+
+        let has_printing = parts.len() > 3
+            && parts[parts.len() - 2].starts_with('(')
+            && parts[parts.len() - 2].ends_with(')');
+
+        let (name, set, collector_number) = match has_printing {
+            true => (
+                parts[1..parts.len() - 2].join(" "),
+                Some(parts[parts.len() - 2].trim_matches(|c: char| !c.is_alphanumeric())),
+                Some(parts[parts.len() - 1]),
+            ),
+            false => (parts[1..].join(" "), None, None),
+        };
+
+        cards.push(OwnedCard {
+            quantity,
+            name,
+            set: set.map(str::to_string),
+            collector_number: collector_number.map(str::to_string),
+            scryfall_id: None,
+        });
+    }
+
+    Ok(cards)
+}
+
+impl Collection {
+    fn key(&self, card: &Card) -> String {
+        match self.by {
+            OwnedMatch::Name => name_key(&card.name),
+            OwnedMatch::Printing => card.scryfall_id.clone(),
+        }
+    }
+}
+
+impl Deck {
+    /// Removes copies of cards that are already owned, returning the names
+    /// and counts of everything that was skipped.
+    pub fn remove_owned(&mut self, collection: &Collection) -> Vec<(String, usize)> {
+        let mut available = collection.owned.clone();
+        let mut skipped: Vec<(String, usize)> = Vec::new();
+
+        for cards in [&mut self.cards, &mut self.tokens] {
+            let mut skipped_ids: HashMap<String, usize> = HashMap::new();
+
+            for card in cards.iter_mut().filter(|card| !card.backface) {
+                let Some(count) = available.get_mut(&collection.key(card)) else {
+                    continue;
+                };
+                let owned = (*count).min(card.quantity as usize);
+                if owned == 0 {
+                    continue;
+                }
+
+                *count -= owned;
+                card.quantity -= owned as u32;
+                *skipped_ids.entry(card.scryfall_id.clone()).or_default() += owned;
+                match skipped.iter_mut().find(|(name, _)| *name == card.name) {
+                    Some((_, skipped)) => *skipped += owned,
+                    None => skipped.push((card.name.clone(), owned)),
+                }
+            }
+
+            // Drop the back face of every skipped double-faced card as well
+            for card in cards.iter_mut().filter(|card| card.backface) {
+                if let Some(count) = skipped_ids.get_mut(&card.scryfall_id) {
+                    let owned = (*count).min(card.quantity as usize);
+                    *count -= owned;
+                    card.quantity -= owned as u32;
+                }
+            }
+
+            cards.retain(|card| card.quantity > 0);
+        }
+
+        skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{back_of, card};
+
+    fn collection(by: OwnedMatch, owned: &[(&str, usize)]) -> Collection {
+        Collection {
+            by,
+            owned: owned
+                .iter()
+                .map(|(key, count)| (key.to_string(), *count))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn owned_copies_are_removed() {
+        let mut deck = Deck::new(
+            "Deck",
+            vec![card("Island", "a", 4), card("Opt", "b", 2)],
+            Vec::new(),
+        );
+        let skipped = deck.remove_owned(&collection(OwnedMatch::Name, &[("island", 3)]));
+
+        assert_eq!(skipped, [("Island".to_string(), 3)]);
+        assert_eq!(deck.cards[0].quantity, 1);
+        assert_eq!(deck.cards[1].quantity, 2);
+    }
+
+    #[test]
+    fn fully_owned_cards_are_dropped() {
+        let mut deck = Deck::new(
+            "Deck",
+            vec![card("Island", "a", 2), card("Opt", "b", 2)],
+            Vec::new(),
+        );
+        deck.remove_owned(&collection(OwnedMatch::Name, &[("island", 5)]));
+
+        assert_eq!(deck.cards.len(), 1);
+        assert_eq!(deck.cards[0].name, "Opt");
+    }
+
+    #[test]
+    fn owned_copies_are_used_up_across_entries() {
+        let sideboard = Card {
+            section: Some("sideboard".to_string()),
+            ..card("Opt", "b", 2)
+        };
+        let mut deck = Deck::new("Deck", vec![card("Opt", "b", 2), sideboard], Vec::new());
+        let skipped = deck.remove_owned(&collection(OwnedMatch::Name, &[("opt", 3)]));
+
+        assert_eq!(skipped, [("Opt".to_string(), 3)]);
+        assert_eq!(deck.cards.len(), 1);
+        assert_eq!(deck.cards[0].quantity, 1);
+    }
+
+    #[test]
+    fn back_face_goes_with_its_front() {
+        let front = card("Delver of Secrets // Insectile Aberration", "d", 2);
+        let back = back_of(&front);
+        let mut deck = Deck::new("Deck", vec![front, back], Vec::new());
+        let skipped = deck.remove_owned(&collection(OwnedMatch::Name, &[("delver of secrets", 1)]));
+
+        assert_eq!(skipped.len(), 1);
+        assert!(deck.cards.iter().all(|card| card.quantity == 1));
+    }
+
+    #[test]
+    fn printing_match_needs_the_same_printing() {
+        let mut deck = Deck::new(
+            "Deck",
+            vec![card("Island", "a", 2), card("Island", "b", 2)],
+            Vec::new(),
+        );
+        deck.remove_owned(&collection(OwnedMatch::Printing, &[("b", 2)]));
+
+        assert_eq!(deck.cards.len(), 1);
+        assert_eq!(deck.cards[0].scryfall_id, "a");
+    }
+
+    #[test]
+    fn owned_tokens_are_removed() {
+        let mut deck = Deck::new("Deck", Vec::new(), vec![card("Treasure", "t", 2)]);
+        deck.remove_owned(&collection(OwnedMatch::Name, &[("treasure", 2)]));
+
+        assert!(deck.tokens.is_empty());
+    }
+
+    #[test]
+    fn list_skips_section_headers_and_finishes() {
+        let list = "Commander\n1 Rock & Roll (NEC) 134 *F*\n\nSIDEBOARD:\n2x Opt\n";
+        let cards = parse_list(list.as_bytes()).unwrap();
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].name, "Rock & Roll");
+        assert_eq!(cards[0].set.as_deref(), Some("NEC"));
+        assert_eq!(cards[0].collector_number.as_deref(), Some("134"));
+        assert_eq!((cards[1].quantity, cards[1].name.as_str()), (2, "Opt"));
+    }
+
+    #[test]
+    fn list_rejects_invalid_quantity() {
+        assert!(parse_list("Opt\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn list_rejects_finish_without_card() {
+        assert!(parse_list("*F*\n".as_bytes()).is_err());
+        assert!(parse_list("2 *F*\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheConfig;
+use crate::deck::{DeckGenerationOptions, ImageVersion, PaperSize, Separator};
+use crate::error::{Error, Result};
+use crate::local::FuzzyMatch;
+#[cfg(feature = "remote")]
+use crate::remote::SourcesConfig;
+
+/// Name of the config file that applies to decks in the current directory.
+pub const PROJECT_CONFIG: &str = "tiffer.toml";
+
+/// Settings read from `$XDG_CONFIG_HOME/tiffer/config.toml`, overridden by
+/// `tiffer.toml` in the current directory.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub print: PrintConfig,
+    pub cache: CacheConfig,
+    #[cfg(feature = "remote")]
+    pub sources: SourcesConfig,
+}
+
+/// Defaults for `DeckGenerationOptions`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PrintConfig {
+    pub print_tokens: bool,
+    pub filename: Option<String>,
+    pub separator: Separator,
+    pub paper: PaperSize,
+    pub image_version: ImageVersion,
+    pub refresh: bool,
+    pub tolerant: bool,
+    pub placeholders: bool,
+    pub fuzzy: FuzzyMatch,
+}
+
+impl Default for PrintConfig {
+    fn default() -> Self {
+        let options = DeckGenerationOptions::default();
+        PrintConfig {
+            print_tokens: options.print_tokens,
+            filename: options.filename,
+            separator: options.separator,
+            paper: options.paper,
+            image_version: options.image_version,
+            refresh: options.refresh,
+            tolerant: options.tolerant,
+            placeholders: options.placeholders,
+            fuzzy: options.fuzzy,
+        }
+    }
+}
+
+impl Config {
+    /// Location of the user's config file, if a home directory is known.
+    pub fn path() -> Option<PathBuf> {
+        let config_home = match std::env::var("XDG_CONFIG_HOME") {
+            Ok(config_home) => PathBuf::from(config_home),
+            Err(_) => PathBuf::from(std::env::var("HOME").ok()?).join(".config"),
+        };
+
+        Some(config_home.join("tiffer").join("config.toml"))
+    }
+
+    /// Config files that exist, in the order they are applied.
+    pub fn files() -> Vec<PathBuf> {
+        Config::path()
+            .into_iter()
+            .chain(std::iter::once(PathBuf::from(PROJECT_CONFIG)))
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    /// Loads the user's and the project's config files, falling back to
+    /// defaults for anything neither of them sets.
+    pub fn load() -> Result<Config> {
+        let mut table = toml::Table::new();
+        for path in Config::files() {
+            merge(&mut table, read_table(&path)?);
+        }
+
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// The same config with credentials hidden, safe to print.
+    pub fn redacted(&self) -> Config {
+        Config {
+            #[cfg(feature = "remote")]
+            sources: self.sources.redacted(),
+            ..self.clone()
+        }
+    }
+
+    /// Generation options with every setting taken from the config.
+    pub fn options(&self) -> DeckGenerationOptions {
+        DeckGenerationOptions {
+            print_tokens: self.print.print_tokens,
+            filename: self.print.filename.clone(),
+            separator: self.print.separator,
+            paper: self.print.paper,
+            image_version: self.print.image_version,
+            cache: self.cache.clone(),
+            refresh: self.print.refresh,
+            tolerant: self.print.tolerant,
+            placeholders: self.print.placeholders,
+            fuzzy: self.print.fuzzy,
+            ..DeckGenerationOptions::default()
+        }
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table> {
+    toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+}
+
+/// Overlays one config on another, key by key, so a project config only
+/// needs to list what it changes.
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -1,25 +1,116 @@
-extern crate printpdf;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
 
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
-use std::sync::Arc;
-use std::time::Duration;
-
-use indicatif::ProgressBar;
-use printpdf::path::{PaintMode, WindingOrder};
-use printpdf::*;
-
-use reqwest::header::{HeaderValue, RETRY_AFTER};
-use reqwest::Client;
+use derive_more::Display;
 use serde::Deserialize;
 use serde::Serialize;
 use tokio::task;
-use tokio::time::sleep;
+
+use crate::cache::{self, CacheConfig, ImageMeta};
+use crate::error::{Error, Failure, FailureStage, Result};
+use crate::local::FuzzyMatch;
+use crate::progress::{Progress, Reporter};
+use crate::scryfall::{FetchedImage, ScryfallClient};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DeckGenerationOptions {
     pub print_tokens: bool,
     pub filename: Option<String>,
+    pub separator: Separator,
+    pub paper: PaperSize,
+    pub image_version: ImageVersion,
+    pub cache: CacheConfig,
+    /// Check cached images for newer versions on Scryfall.
+    pub refresh: bool,
+    /// Leave out cards whose image can't be downloaded instead of failing.
+    pub tolerant: bool,
+    /// Print a labelled outline in place of every card that was left out.
+    pub placeholders: bool,
+    /// What to do with cards whose name only matches approximately.
+    pub fuzzy: FuzzyMatch,
+    /// Where progress, messages and events go, nowhere by default.
+    #[serde(skip)]
+    pub reporter: Reporter,
+}
+
+impl DeckGenerationOptions {
+    /// The cache for the chosen image version, each version is cached separately.
+    pub fn image_cache(&self) -> CacheConfig {
+        self.cache.for_version(self.image_version)
+    }
+}
+
+impl Default for DeckGenerationOptions {
+    fn default() -> Self {
+        DeckGenerationOptions {
+            print_tokens: true,
+            filename: None,
+            separator: Separator::default(),
+            paper: PaperSize::default(),
+            image_version: ImageVersion::default(),
+            cache: CacheConfig::default(),
+            refresh: false,
+            tolerant: false,
+            placeholders: false,
+            fuzzy: FuzzyMatch::default(),
+            reporter: Reporter::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    /// 210 x 297 mm
+    #[default]
+    A4,
+    /// 297 x 420 mm
+    A3,
+    /// 8.5 x 11 in
+    Letter,
+    /// 8.5 x 14 in
+    Legal,
+}
+
+/// Which of Scryfall's JPEG image sizes to print.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ImageVersion {
+    /// 146 x 204 pixels
+    Small,
+    /// 488 x 680 pixels
+    Normal,
+    /// 672 x 936 pixels
+    #[default]
+    Large,
+}
+
+impl ImageVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageVersion::Small => "small",
+            ImageVersion::Normal => "normal",
+            ImageVersion::Large => "large",
+        }
+    }
+}
+
+/// How the individual decks of a merged print job are set apart in the PDF.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Separator {
+    /// Print all cards back to back, filling every sheet.
+    #[default]
+    None,
+    /// Insert a page with the deck name before each deck.
+    Page,
+    /// Start each deck on a new page with its name above the cards.
+    Header,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,310 +118,742 @@ pub struct Deck {
     pub name: String,
     pub cards: Vec<Card>,
     pub tokens: Vec<Card>,
+    /// Names of the decks this deck was merged from, indexed by `Card::part`.
+    #[serde(default)]
+    pub parts: Vec<String>,
+    /// Cards that couldn't be resolved, left out of the deck.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<Failure>,
 }
 
 impl Deck {
-    pub fn total_cards(&self) -> usize {
-        self.cards.len() + self.tokens.len()
+    /// A deck that wasn't merged from others and had no failures.
+    pub fn new(name: impl Into<String>, cards: Vec<Card>, tokens: Vec<Card>) -> Deck {
+        Deck {
+            name: name.into(),
+            cards,
+            tokens,
+            parts: Vec::new(),
+            failures: Vec::new(),
+        }
     }
 
-    fn cache_dir(&self) -> String {
-        format!(
-            "{}/tiffer/cards",
-            std::env::var("XDG_CACHE_HOME").unwrap_or_else(|_| {
-                format!(
-                    "{}/.cache",
-                    std::env::var("HOME").expect("HOME environment variable not set")
-                )
-            })
-        )
-    }
+    /// Merges several decks into a single print job, keeping track of which
+    /// deck every card came from.
+    pub fn merge(decks: Vec<Deck>) -> Deck {
+        if decks.len() == 1 {
+            return decks.into_iter().next().unwrap();
+        }
 
-    pub async fn download(&self, options: DeckGenerationOptions) -> anyhow::Result<()> {
-        let cache_dir = self.cache_dir();
-        fs::create_dir_all(&cache_dir)?;
+        let name = decks
+            .iter()
+            .map(|deck| deck.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" + ");
+
+        let mut merged = Deck::new(name, Vec::new(), Vec::new());
+
+        for (part, deck) in decks.into_iter().enumerate() {
+            merged.parts.push(deck.name);
+            merged.failures.extend(deck.failures);
+            merged
+                .cards
+                .extend(deck.cards.into_iter().map(|card| Card { part, ..card }));
+            merged
+                .tokens
+                .extend(deck.tokens.into_iter().map(|card| Card { part, ..card }));
+        }
 
-        // Create reqwest client
-        let client = reqwest::Client::builder()
-            .user_agent("curl/7.68.0")
-            .default_headers({
-                let mut headers = reqwest::header::HeaderMap::new();
-                headers.insert(
-                    reqwest::header::ACCEPT,
-                    reqwest::header::HeaderValue::from_static("image/jpeg"),
-                );
-                headers
-            })
-            .build()?;
+        merged
+    }
 
-        // Wrap the client in an Arc to share between tasks
-        let client = Arc::new(client);
+    /// Number of cards printed, back faces and tokens included.
+    pub fn total_cards(&self) -> usize {
+        self.card_count() + self.token_count()
+    }
 
-        // Create a vector of tasks
-        let mut tasks = Vec::new();
+    /// Copies of all printed cards, back faces included.
+    pub fn card_count(&self) -> usize {
+        copies(&self.cards)
+    }
 
-        let bar = ProgressBar::new(self.total_cards() as u64);
+    pub fn token_count(&self) -> usize {
+        copies(&self.tokens)
+    }
 
+    /// Every copy in the order they are printed: each part's cards followed
+    /// by its tokens, back faces right after their front.
+    pub(crate) fn print_order(&self, print_tokens: bool) -> Vec<Card> {
         let mut cards = Vec::new();
-        cards.extend(self.cards.iter().cloned()); // assuming `Card` implements `Clone`
-        if options.print_tokens {
-            cards.extend(self.tokens.iter().cloned()); // assuming `Token` implements `Clone`
+        for part in 0..self.parts.len().max(1) {
+            expand(&mut cards, &self.cards, part);
+            if print_tokens {
+                expand(&mut cards, &self.tokens, part);
+            }
         }
+        cards
+    }
 
-        println!("Downloading cards...");
-        for card in cards {
-            let client = Arc::clone(&client);
-            let card = card.clone(); // Assuming `Card` implements `Clone`
-            let cache_dir = cache_dir.clone();
-            let bar = bar.clone();
-
-            let task = task::spawn(async move {
-                let download = card.download(cache_dir.clone(), &client).await;
-                bar.inc(1);
+    /// Downloads every image the deck needs into the cache. In tolerant mode
+    /// the cards that failed are returned rather than aborting the download.
+    pub async fn download(&self, options: DeckGenerationOptions) -> Result<Vec<Failure>> {
+        let cache_dir = options.image_cache().dir()?;
+        fs::create_dir_all(&cache_dir)?;
 
-                download
-            });
+        options.reporter.status("Downloading cards...");
+        let (_, failures) = self
+            .for_each_image(&options, |card, options, client| async move {
+                card.download(&options, client).await
+            })
+            .await?;
 
-            tasks.push(task);
-        }
+        Ok(failures)
+    }
 
-        // Await all tasks
-        for task in tasks {
-            task.await??;
+    /// Downloads every image the deck needs and returns their contents. In
+    /// tolerant mode cards whose image couldn't be downloaded are missing
+    /// from the result and listed in `CardImages::failures`.
+    ///
+    /// Images go through the cache like with `download`, unless there is no
+    /// cache directory to use, in which case they are only kept in memory.
+    pub async fn fetch_images(&self, options: DeckGenerationOptions) -> Result<CardImages> {
+        let cache = options.image_cache();
+        if cache.dir().is_err() {
+            options.reporter.status("Downloading cards...");
+            let (images, failures) = self
+                .for_each_image(&options, |card, options, client| async move {
+                    card.fetch_image(&options, client).await
+                })
+                .await?;
+
+            return Ok(CardImages {
+                images: images.into_iter().collect(),
+                failures,
+            });
         }
 
-        Ok(())
-    }
+        let failures = self.download(options.clone()).await?;
+        let mut images = CardImages {
+            failures,
+            ..CardImages::default()
+        };
+        for card in self.print_order(options.print_tokens) {
+            if images.get(&card).is_some() {
+                continue;
+            }
 
-    pub async fn generate(&self, options: DeckGenerationOptions) -> anyhow::Result<()> {
-        println!(
-            "Generating deck: {}",
-            &options
-                .filename
-                .clone()
-                .unwrap_or_else(|| self.name.clone())
-        );
-        match options.print_tokens {
-            true => println!(
-                "Total cards: {} ({} mainboard, {} tokens)",
-                self.total_cards(),
-                self.cards.len(),
-                self.tokens.len()
-            ),
-            false => {
-                println!("Skipping tokens...");
-                println!(
-                    "Total cards: {} ({} mainboard)",
-                    self.total_cards(),
-                    self.cards.len()
-                )
+            match cache.find(&card)? {
+                Some(path) => images.insert(&card, fs::read(path)?),
+                // The download failed and is in the failures already
+                None if options.tolerant => {}
+                None => {
+                    return Err(Error::ImageDecode {
+                        card: card.name.clone(),
+                        message: "image missing from the cache".to_string(),
+                    })
+                }
             }
         }
 
-        self.download(options.clone()).await?;
-
-        self.pdf(options)?;
-
-        Ok(())
+        Ok(images)
     }
 
-    fn pdf(&self, options: DeckGenerationOptions) -> anyhow::Result<()> {
-        let cache_dir = self.cache_dir();
-
-        let (doc, page_idx, layer_idx) = PdfDocument::new("Deck", Mm(210.0), Mm(297.0), "Layer");
+    /// Runs `fetch` concurrently for every distinct image the deck needs. In
+    /// tolerant mode the cards it fails for are returned rather than an error.
+    async fn for_each_image<T, F, Fut>(
+        &self,
+        options: &DeckGenerationOptions,
+        fetch: F,
+    ) -> Result<(Vec<(ImageKey, T)>, Vec<Failure>)>
+    where
+        F: Fn(Card, DeckGenerationOptions, &'static ScryfallClient) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let client = ScryfallClient::shared()?;
+
+        // Every copy of a card shares one image, only fetch it once
+        let mut cards = self.print_order(options.print_tokens);
+        let mut copies: HashMap<ImageKey, u32> = HashMap::new();
+        for card in &cards {
+            *copies.entry(ImageKey::of(card)).or_default() += 1;
+        }
+        let mut seen = HashSet::new();
+        cards.retain(|card| seen.insert(ImageKey::of(card)));
+
+        let progress = Progress::new(&options.reporter, "download", cards.len() as u64);
+
+        let tasks: Vec<_> = cards
+            .into_iter()
+            .map(|card| {
+                let key = ImageKey::of(&card);
+                let name = card.name.clone();
+                let fetch = fetch(card, options.clone(), client);
+                let progress = progress.clone();
+
+                task::spawn(async move {
+                    let result = fetch.await;
+                    progress.inc(&name);
+                    (key, name, result)
+                })
+            })
+            .collect();
 
-        let (card_width, card_height) = (Mm(63.0), Mm(87.8));
+        let mut fetched = Vec::new();
+        let mut failures = Vec::new();
+        for task in tasks {
+            match task.await? {
+                (key, _, Ok(value)) => fetched.push((key, value)),
+                (key, card, Err(e)) if options.tolerant => failures.push(Failure {
+                    card,
+                    quantity: copies[&key],
+                    stage: FailureStage::Download,
+                    error: e.to_string(),
+                }),
+                (_, _, Err(e)) => return Err(e),
+            }
+        }
+        progress.finish();
 
-        let num_cards_in_row = 3;
-        let total_row_width = Mm(num_cards_in_row as f32 * card_width.0);
-        let mut x = (Mm(210.0) - total_row_width) / 2.0;
-        let mut y = Mm(297.0) - card_height - Mm(15.0);
+        Ok((fetched, failures))
+    }
+}
 
-        let mut layer = doc.get_page(page_idx).get_layer(layer_idx);
+fn copies(cards: &[Card]) -> usize {
+    cards
+        .iter()
+        .filter(|card| card.is_printed())
+        .map(|card| card.quantity as usize)
+        .sum()
+}
 
-        // reference size box for card
-        let points = vec![
-            (Point::new(Mm(0.0), Mm(0.0)), false),
-            (Point::new(card_width, Mm(0.0)), false),
-            (Point::new(card_width, card_height), false),
-            (Point::new(Mm(0.0), card_height), false),
-        ];
-        let _line = Polygon {
-            rings: vec![points],
-            mode: PaintMode::FillStroke,
-            winding_order: WindingOrder::NonZero,
-        };
+/// Adds every copy of the cards of a part, one `Card` with quantity 1 each.
+/// Every copy of a front is followed by a copy of its back face, wherever
+/// the back face is listed; back faces without a front come last.
+fn expand(copies: &mut Vec<Card>, cards: &[Card], part: usize) {
+    let cards: Vec<&Card> = cards
+        .iter()
+        .filter(|card| card.part == part && card.is_printed())
+        .collect();
+    let single = |card: &Card| Card {
+        quantity: 1,
+        ..card.clone()
+    };
+
+    // Copies of every back face not printed yet
+    let mut backs: Vec<(&Card, u32)> = cards
+        .iter()
+        .filter(|card| card.backface)
+        .map(|card| (*card, card.quantity))
+        .collect();
+
+    for front in cards.iter().filter(|card| !card.backface) {
+        for _ in 0..front.quantity {
+            copies.push(single(front));
+
+            let back = backs
+                .iter_mut()
+                .find(|(back, left)| *left > 0 && back.is_back_of(front));
+            if let Some((back, left)) = back {
+                copies.push(single(back));
+                *left -= 1;
+            }
+        }
+    }
 
-        // layer.add_polygon(line);
+    for (back, left) in backs {
+        copies.extend((0..left).map(|_| single(back)));
+    }
+}
 
-        let bar = ProgressBar::new(match options.print_tokens {
-            true => self.total_cards() as u64,
-            false => self.cards.len() as u64,
-        });
-        println!("Rendering cards...");
+/// Identifies the image of a card, which all copies of the card share.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ImageKey {
+    pub scryfall_id: String,
+    pub backface: bool,
+    pub lang: String,
+}
 
-        let mut cards = Vec::new();
-        cards.extend(self.cards.iter().cloned());
-        if options.print_tokens {
-            cards.extend(self.tokens.iter().cloned());
+impl ImageKey {
+    pub fn of(card: &Card) -> Self {
+        ImageKey {
+            scryfall_id: card.scryfall_id.clone(),
+            backface: card.backface,
+            lang: card.lang.clone().unwrap_or_else(|| "en".to_string()),
         }
-        for card in cards {
-            log::debug!("Rendering {}", card.name);
-            let mut image_file = BufReader::new(
-                File::open(card.cached_path(cache_dir.clone())).expect("Failed to open file"),
-            );
-            let image = Image::try_from(
-                image_crate::codecs::jpeg::JpegDecoder::new(&mut image_file).unwrap(),
-            )
-            .unwrap();
+    }
+}
 
-            if x + card_width > Mm(210.0) {
-                x = (Mm(210.0) - total_row_width) / 2.0;
-                y -= card_height;
-            }
+/// JPEG images of the cards of a deck, as fetched by `Deck::fetch_images`.
+#[derive(Debug, Clone, Default)]
+pub struct CardImages {
+    images: HashMap<ImageKey, Vec<u8>>,
+    /// Cards whose image couldn't be downloaded in tolerant mode.
+    pub failures: Vec<Failure>,
+}
 
-            if y < Mm(0.0) {
-                let (new_page_idx, _) = doc.add_page(Mm(210.0), Mm(297.0), "Layer 1");
-                layer = doc.get_page(new_page_idx).get_layer(layer_idx);
-                y = Mm(297.0) - card_height - Mm(15.0);
-            }
+impl CardImages {
+    pub fn get(&self, card: &Card) -> Option<&[u8]> {
+        self.images.get(&ImageKey::of(card)).map(Vec::as_slice)
+    }
 
-            image.add_to_layer(
-                layer.clone(),
-                ImageTransform {
-                    translate_x: Some(x),
-                    translate_y: Some(y),
-                    dpi: Some(1200.0),
-                    scale_x: Some(4.42),
-                    scale_y: Some(4.42),
-                    rotate: Some(ImageRotation::default()),
-                },
-            );
+    /// Sets the JPEG image printed for `card` and every copy of it.
+    pub fn insert(&mut self, card: &Card, image: Vec<u8>) {
+        self.images.insert(ImageKey::of(card), image);
+    }
 
-            x += card_width;
+    pub fn iter(&self) -> impl Iterator<Item = (&ImageKey, &[u8])> {
+        self.images
+            .iter()
+            .map(|(key, image)| (key, image.as_slice()))
+    }
 
-            bar.inc(1);
-        }
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
 
-        let filename = options
-            .filename
-            .clone()
-            .unwrap_or_else(|| format!("{}.pdf", self.name));
-        let mut buffer = BufWriter::new(File::create(filename).unwrap());
-        doc.save(&mut buffer).unwrap();
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+}
 
-        Ok(())
+impl IntoIterator for CardImages {
+    type Item = (ImageKey, Vec<u8>);
+    type IntoIter = std::collections::hash_map::IntoIter<ImageKey, Vec<u8>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.images.into_iter()
     }
 }
 
+/// One line of a deck: a printing and how many copies of it to use.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Card {
     pub name: String,
     pub scryfall_id: String,
     pub backface: bool,
+    #[serde(default = "one")]
+    pub quantity: u32,
+    /// Index into `Deck::parts` of the deck this card was merged from.
+    #[serde(default)]
+    pub part: usize,
+    /// Language of the printing, `None` for Scryfall's default English printing.
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Whether the printing was chosen explicitly rather than picked by the source.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Set code of the printing, e.g. "nec", if the source told us.
+    #[serde(default)]
+    pub set: Option<String>,
+    #[serde(default)]
+    pub collector_number: Option<String>,
+    #[serde(default)]
+    pub finish: Option<Finish>,
+    /// Scryfall's layout, e.g. "normal", "transform" or "adventure".
+    #[serde(default)]
+    pub layout: Option<String>,
+    /// e.g. "Creature — Hydra"
+    #[serde(default)]
+    pub type_line: Option<String>,
+    /// Section of the decklist, e.g. "sideboard" or "commander", `None` for the main deck.
+    #[serde(default)]
+    pub section: Option<String>,
+    /// Scryfall's `image_status` of the printing when it was looked up, stored
+    /// with the cached image so that `--refresh` knows to replace low-res scans.
+    #[serde(default)]
+    pub image_status: Option<String>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Default for Card {
+    fn default() -> Self {
+        Card {
+            name: String::new(),
+            scryfall_id: String::new(),
+            backface: false,
+            quantity: 1,
+            part: 0,
+            lang: None,
+            pinned: false,
+            set: None,
+            collector_number: None,
+            finish: None,
+            layout: None,
+            type_line: None,
+            section: None,
+            image_status: None,
+        }
+    }
+}
+
+/// Surface of a printing, as in Scryfall's `finishes`.
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Finish {
+    #[display("nonfoil")]
+    #[serde(alias = "nonFoil")]
+    Nonfoil,
+    #[display("foil")]
+    Foil,
+    #[display("etched")]
+    Etched,
 }
 
 impl Card {
-    pub fn image_url(&self) -> String {
+    /// Cards in the maybeboard are only being considered for the deck, they
+    /// are listed and exported but not printed.
+    pub fn is_printed(&self) -> bool {
+        self.section.as_deref() != Some("maybeboard")
+    }
+
+    /// Whether this is the back face of `front`, printed along with it.
+    pub fn is_back_of(&self, front: &Card) -> bool {
+        self.backface
+            && !front.backface
+            && self.scryfall_id == front.scryfall_id
+            && self.lang == front.lang
+    }
+
+    pub fn image_url(&self, version: ImageVersion) -> String {
         if self.backface {
             return format!(
-                "https://api.scryfall.com/cards/{}/?format=image&face=back",
-                self.scryfall_id
+                "https://api.scryfall.com/cards/{}/?format=image&version={}&face=back",
+                self.scryfall_id,
+                version.as_str()
             );
         }
 
         format!(
-            "https://api.scryfall.com/cards/{}/?format=image",
-            self.scryfall_id
+            "https://api.scryfall.com/cards/{}/?format=image&version={}",
+            self.scryfall_id,
+            version.as_str()
         )
     }
 
     pub fn cached_path(&self, cache: String) -> String {
+        let cache = match &self.lang {
+            Some(lang) if lang != "en" => format!("{}/{}", cache, lang),
+            _ => cache,
+        };
+
         match self.backface {
             false => format!("{}/{}.jpg", cache, self.scryfall_id),
             true => format!("{}/{}_back.jpg", cache, self.scryfall_id),
         }
     }
 
-    pub async fn download(&self, cache: String, client: &Client) -> anyhow::Result<()> {
-        let file_path = self.cached_path(cache);
+    pub async fn download(
+        &self,
+        options: &DeckGenerationOptions,
+        client: &ScryfallClient,
+    ) -> Result<()> {
+        let cache = options.image_cache();
+
+        if let Some(cached) = cache.find(self)? {
+            if options.refresh {
+                return self.refresh(&cached, options, client).await;
+            }
 
-        if fs::metadata(&file_path).is_ok() {
             log::debug!("Skipping {}", self.name);
+            cache::touch(&cached);
             return Ok(());
         }
 
+        let file_path = self.cached_path(cache.dir()?);
+
+        if fs::metadata(&file_path).is_ok() {
+            options.reporter.warning(&format!(
+                "Cached image of {} is corrupt, downloading again",
+                self.name
+            ));
+        }
+
         log::debug!("Downloading {}", self.name);
 
-        const MAX_RETRIES: usize = 5;
-
-        let mut attempts = 0;
-
-        while attempts < MAX_RETRIES {
-            attempts += 1;
-            let response = client.get(self.image_url()).send().await;
-
-            match response {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        let mut file = fs::File::create(&file_path)?;
-                        std::io::copy(&mut resp.bytes().await?.as_ref(), &mut file)?;
-                        log::debug!("Successfully downloaded {}", self.name);
-                        return Ok(());
-                    } else if resp.status().as_u16() == 429 {
-                        // Rate limit exceeded, retry after backoff
-                        println!("Rate limit exceeded, retrying {}...", self.name);
-                        if let Some(retry_after) = resp.headers().get(RETRY_AFTER) {
-                            let delay = parse_retry_after_header(retry_after)?;
-                            sleep(Duration::from_secs(delay)).await;
-                        } else {
-                            // Default backoff if Retry-After header is missing
-                            let delay = 2u64.pow(attempts as u32);
-                            sleep(Duration::from_secs(delay)).await;
-                        }
-                    } else {
-                        // Other errors, return the result
-                        return Err(anyhow::anyhow!(
-                            "Failed to download {}: {}",
-                            self.name,
-                            resp.status()
-                        ));
-                    }
-                }
-                Err(e) => {
-                    // Handle request error
-                    println!("Error occurred while downloading {}: {}", self.name, e);
-                    if attempts >= MAX_RETRIES {
-                        return Err(anyhow::anyhow!(
-                            "Failed to download {} after {} attempts",
-                            self.name,
-                            MAX_RETRIES
-                        ));
-                    }
-                    // Default backoff if request fails
-                    let delay = 2u64.pow(attempts as u32);
-                    sleep(Duration::from_secs(delay)).await;
-                }
+        let image = self.get_image(options, client).await?;
+        self.store(&file_path, image, self.image_status.clone())?;
+        log::debug!("Successfully downloaded {}", self.name);
+
+        Ok(())
+    }
+
+    /// Downloads the image without caching it.
+    async fn fetch_image(
+        &self,
+        options: &DeckGenerationOptions,
+        client: &ScryfallClient,
+    ) -> Result<Vec<u8>> {
+        let image = self.get_image(options, client).await?;
+        self.validate(&image.data)?;
+        Ok(image.data)
+    }
+
+    async fn get_image(
+        &self,
+        options: &DeckGenerationOptions,
+        client: &ScryfallClient,
+    ) -> Result<FetchedImage> {
+        client
+            .get_image(&self.image_url(options.image_version))
+            .await
+            .map_err(|e| match e {
+                Error::Network { url, message } => Error::network(
+                    url,
+                    format!("failed to download {}: {}", self.name, message),
+                ),
+                e => e,
+            })
+    }
+
+    fn validate(&self, image: &[u8]) -> Result<()> {
+        match cache::is_valid_image(image) {
+            true => Ok(()),
+            false => Err(Error::ImageDecode {
+                card: self.name.clone(),
+                message: "downloaded file is not a valid JPEG image".to_string(),
+            }),
+        }
+    }
+
+    /// Checks whether Scryfall has a newer image than the cached one, which
+    /// happens when preview scans are replaced by proper ones.
+    async fn refresh(
+        &self,
+        cached: &str,
+        options: &DeckGenerationOptions,
+        client: &ScryfallClient,
+    ) -> Result<()> {
+        #[derive(Debug, Deserialize)]
+        struct ScryfallImageStatus {
+            image_status: String,
+        }
+
+        log::debug!("Refreshing {}", self.name);
+
+        let file_path = self.cached_path(options.image_cache().dir()?);
+        let image_url = self.image_url(options.image_version);
+        let meta = cache::read_meta(cached).unwrap_or_default();
+
+        let image_status = client
+            .get_json::<ScryfallImageStatus>(
+                &format!("https://api.scryfall.com/cards/{}", self.scryfall_id),
+                &[],
+            )
+            .await?
+            .map(|card| card.image_status);
+
+        let image = match (&meta.image_status, &image_status) {
+            (Some(old), Some(new)) if image_quality(new) > image_quality(old) => {
+                Some(client.get_image(&image_url).await?)
             }
+            _ => {
+                client
+                    .get_image_if_modified(
+                        &image_url,
+                        meta.etag.as_deref(),
+                        meta.last_modified.as_deref(),
+                    )
+                    .await?
+            }
+        };
+
+        match image {
+            Some(image) => {
+                options
+                    .reporter
+                    .status(&format!("Updated image of {}", self.name));
+                self.store(&file_path, image, image_status)
+            }
+            // Images in the shared cache are left alone, it is read-only
+            None if cached == file_path => {
+                cache::touch(cached);
+                cache::write_meta(
+                    cached,
+                    &ImageMeta {
+                        image_status,
+                        ..meta
+                    },
+                )
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Validates a downloaded image and writes it into the cache.
+    fn store(
+        &self,
+        file_path: &str,
+        image: FetchedImage,
+        image_status: Option<String>,
+    ) -> Result<()> {
+        self.validate(&image.data)?;
+
+        if let Some(dir) = Path::new(file_path).parent() {
+            fs::create_dir_all(dir)?;
         }
 
-        Err(anyhow::anyhow!(
-            "Failed to download {} after {} attempts",
-            self.name,
-            MAX_RETRIES
-        ))
+        cache::write_atomic(file_path, &image.data)?;
+        cache::write_meta(
+            file_path,
+            &ImageMeta {
+                image_status,
+                etag: image.etag,
+                last_modified: image.last_modified,
+            },
+        )
     }
 }
 
-// Helper function to parse Retry-After header
-fn parse_retry_after_header(header: &HeaderValue) -> anyhow::Result<u64> {
-    if let Ok(s) = header.to_str() {
-        if let Ok(seconds) = s.parse::<u64>() {
-            return Ok(seconds);
-        }
-        // Retry-After header is a date-time
-        // Parse the date-time format if needed
+/// Ranks Scryfall's `image_status` values from worst to best.
+fn image_quality(image_status: &str) -> u8 {
+    match image_status {
+        "missing" => 0,
+        "placeholder" => 1,
+        "lowres" => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{back_of, card};
+
+    fn names(cards: &[Card]) -> Vec<String> {
+        cards
+            .iter()
+            .map(|card| match card.backface {
+                true => format!("{} (back)", card.name),
+                false => card.name.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn copies_in_print_order() {
+        let deck = Deck::new(
+            "Deck",
+            vec![card("Opt", "o", 2), card("Island", "i", 1)],
+            vec![card("Treasure", "t", 1)],
+        );
+
+        assert_eq!(
+            names(&deck.print_order(true)),
+            ["Opt", "Opt", "Island", "Treasure"]
+        );
+        assert_eq!(names(&deck.print_order(false)), ["Opt", "Opt", "Island"]);
+        assert!(deck.print_order(true).iter().all(|card| card.quantity == 1));
+    }
+
+    #[test]
+    fn back_face_follows_its_front_wherever_listed() {
+        let delver = card("Delver", "d", 2);
+        let deck = Deck::new(
+            "Deck",
+            vec![back_of(&delver), card("Opt", "o", 1), delver],
+            Vec::new(),
+        );
+
+        assert_eq!(
+            names(&deck.print_order(true)),
+            ["Opt", "Delver", "Delver (back)", "Delver", "Delver (back)"]
+        );
+    }
+
+    #[test]
+    fn back_faces_shared_between_entries() {
+        let delver = card("Delver", "d", 1);
+        let sideboard = Card {
+            section: Some("sideboard".to_string()),
+            ..delver.clone()
+        };
+        let back = Card {
+            quantity: 2,
+            ..back_of(&delver)
+        };
+        let deck = Deck::new("Deck", vec![delver, back, sideboard], Vec::new());
+
+        assert_eq!(
+            names(&deck.print_order(true)),
+            ["Delver", "Delver (back)", "Delver", "Delver (back)"]
+        );
+    }
+
+    #[test]
+    fn unpaired_back_faces_come_last() {
+        let delver = card("Delver", "d", 1);
+        let back = Card {
+            quantity: 2,
+            ..back_of(&delver)
+        };
+        let deck = Deck::new("Deck", vec![back, delver, card("Opt", "o", 1)], Vec::new());
+
+        assert_eq!(
+            names(&deck.print_order(true)),
+            ["Delver", "Delver (back)", "Opt", "Delver (back)"]
+        );
+    }
+
+    #[test]
+    fn merged_decks_print_one_after_the_other() {
+        let delver = card("Delver", "d", 1);
+        let first = Deck::new(
+            "First",
+            vec![delver.clone(), card("Opt", "o", 1)],
+            vec![card("Treasure", "t", 1)],
+        );
+        // Same double-faced card in the second deck, its back listed first
+        let second = Deck::new("Second", vec![back_of(&delver), delver], Vec::new());
+
+        let merged = Deck::merge(vec![first, second]);
+
+        assert_eq!(merged.name, "First + Second");
+        assert_eq!(merged.parts, ["First", "Second"]);
+        assert_eq!(merged.card_count(), 4);
+        assert_eq!(merged.total_cards(), 5);
+        assert_eq!(
+            names(&merged.print_order(true)),
+            ["Delver", "Opt", "Treasure", "Delver", "Delver (back)"]
+        );
+        assert_eq!(
+            merged
+                .print_order(true)
+                .iter()
+                .map(|card| card.part)
+                .collect::<Vec<_>>(),
+            [0, 0, 0, 1, 1]
+        );
+    }
+
+    #[test]
+    fn maybeboard_is_not_printed() {
+        let maybe = Card {
+            section: Some("maybeboard".to_string()),
+            ..card("Ponder", "p", 2)
+        };
+        let token = Card {
+            section: Some("maybeboard".to_string()),
+            ..card("Treasure", "t", 1)
+        };
+        let deck = Deck::new("Deck", vec![card("Opt", "o", 1), maybe], vec![token]);
+
+        assert_eq!(names(&deck.print_order(true)), ["Opt"]);
+        assert_eq!(deck.total_cards(), 1);
+    }
+
+    #[test]
+    fn back_face_in_another_language_is_not_paired() {
+        let delver = card("Delver", "d", 1);
+        let back = Card {
+            lang: Some("ja".to_string()),
+            ..back_of(&delver)
+        };
+
+        assert!(!back.is_back_of(&delver));
+        assert!(back_of(&delver).is_back_of(&delver));
     }
-    // Return a default value if parsing fails
-    Ok(60) // Default to 60 seconds
 }
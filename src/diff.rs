@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use crate::deck::{Card, Deck};
+
+/// What identifies two cards as "the same" when comparing decks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DiffKey {
+    /// Exact printing, so swapping to a different printing counts as a change.
+    #[default]
+    Printing,
+    /// Card name, ignoring which printing is used.
+    Name,
+}
+
+#[derive(Debug)]
+pub struct DeckDiff {
+    /// Cards and tokens present in the new deck but not in the old one.
+    pub added: Deck,
+    /// Names and counts of cards present in the old deck but not in the new one.
+    pub removed: Vec<(String, usize)>,
+}
+
+impl DiffKey {
+    fn of(&self, card: &Card) -> (String, bool) {
+        match self {
+            DiffKey::Printing => (card.scryfall_id.clone(), card.backface),
+            DiffKey::Name => (card.name.clone(), card.backface),
+        }
+    }
+}
+
+impl Deck {
+    /// Compares this deck against a newer version of it, by card and by quantity.
+    pub fn diff(&self, new: &Deck, key: DiffKey) -> DeckDiff {
+        let (cards, mut removed) = diff_cards(&self.cards, &new.cards, key);
+        let (tokens, _) = diff_cards(&self.tokens, &new.tokens, key);
+
+        // Back faces are printed alongside their front, no need to list them twice
+        removed.retain(|(card, _)| !card.backface);
+
+        DeckDiff {
+            added: Deck {
+                failures: new.failures.clone(),
+                ..Deck::new(format!("{} (changes)", new.name), cards, tokens)
+            },
+            removed: removed
+                .into_iter()
+                .map(|(card, count)| (card.name, count))
+                .collect(),
+        }
+    }
+}
+
+fn diff_cards(old: &[Card], new: &[Card], key: DiffKey) -> (Vec<Card>, Vec<(Card, usize)>) {
+    let mut remaining: HashMap<(String, bool), usize> = HashMap::new();
+    for card in old {
+        *remaining.entry(key.of(card)).or_default() += card.quantity as usize;
+    }
+
+    let mut added = Vec::new();
+    for card in new {
+        let count = remaining.entry(key.of(card)).or_default();
+        let kept = (*count).min(card.quantity as usize);
+        *count -= kept;
+
+        if kept < card.quantity as usize {
+            added.push(Card {
+                part: 0,
+                quantity: card.quantity - kept as u32,
+                ..card.clone()
+            });
+        }
+    }
+
+    // Walk the old deck again so removals come out in decklist order
+    let mut removed = Vec::new();
+    for card in old {
+        if let Some(count) = remaining.remove(&key.of(card)) {
+            if count > 0 {
+                removed.push((card.clone(), count));
+            }
+        }
+    }
+
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{back_of, card};
+
+    #[test]
+    fn same_cards_no_changes() {
+        let cards = vec![card("Island", "a", 4), card("Opt", "b", 2)];
+        let (added, removed) = diff_cards(&cards, &cards, DiffKey::Printing);
+
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn quantities_are_compared() {
+        let old = vec![card("Island", "a", 4), card("Opt", "b", 3)];
+        let new = vec![card("Island", "a", 6), card("Opt", "b", 1)];
+        let (added, removed) = diff_cards(&old, &new, DiffKey::Printing);
+
+        assert_eq!(added.len(), 1);
+        assert_eq!((added[0].name.as_str(), added[0].quantity), ("Island", 2));
+        assert_eq!(removed.len(), 1);
+        assert_eq!((removed[0].0.name.as_str(), removed[0].1), ("Opt", 2));
+    }
+
+    #[test]
+    fn removals_in_decklist_order() {
+        let old = vec![
+            card("Opt", "b", 1),
+            card("Island", "a", 1),
+            card("Ponder", "c", 1),
+        ];
+        let (_, removed) = diff_cards(&old, &[], DiffKey::Printing);
+
+        let names: Vec<&str> = removed.iter().map(|(card, _)| card.name.as_str()).collect();
+        assert_eq!(names, ["Opt", "Island", "Ponder"]);
+    }
+
+    #[test]
+    fn copies_split_across_entries() {
+        let old = vec![card("Island", "a", 2), card("Island", "a", 2)];
+        let new = vec![card("Island", "a", 3)];
+        let (added, removed) = diff_cards(&old, &new, DiffKey::Printing);
+
+        assert!(added.is_empty());
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].1, 1);
+    }
+
+    #[test]
+    fn other_printing_by_key() {
+        let old = vec![card("Island", "a", 4)];
+        let new = vec![card("Island", "b", 4)];
+
+        let (added, removed) = diff_cards(&old, &new, DiffKey::Printing);
+        assert_eq!(added.len(), 1);
+        assert_eq!(removed.len(), 1);
+
+        let (added, removed) = diff_cards(&old, &new, DiffKey::Name);
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn added_cards_belong_to_the_new_deck() {
+        let new = vec![Card {
+            part: 2,
+            ..card("Opt", "b", 1)
+        }];
+        let (added, _) = diff_cards(&[], &new, DiffKey::Printing);
+
+        assert_eq!(added[0].part, 0);
+    }
+
+    #[test]
+    fn back_faces_not_listed_as_removed() {
+        let front = card("Delver of Secrets", "d", 1);
+        let back = back_of(&front);
+        let old = Deck::new("Deck", vec![front, back], Vec::new());
+        let diff = old.diff(
+            &Deck::new("Deck", Vec::new(), Vec::new()),
+            DiffKey::Printing,
+        );
+
+        assert_eq!(diff.removed, [("Delver of Secrets".to_string(), 1)]);
+        assert_eq!(diff.added.name, "Deck (changes)");
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while importing decks and generating prints.
+#[derive(Debug, Display, Error)]
+pub enum Error {
+    /// A decklist, collection or deck website response couldn't be understood.
+    #[display("Failed to parse {input}: {message}")]
+    SourceParse { input: String, message: String },
+    #[display("Unsupported deck website: {_0}")]
+    UnsupportedWebsite(String),
+    #[display("Request to {url} failed: {message}")]
+    Network { url: String, message: String },
+    #[display("Rate limit exceeded for {url}, gave up after {attempts} attempts")]
+    RateLimited { url: String, attempts: u32 },
+    #[display("Card not found: {_0}")]
+    CardNotFound(String),
+    /// The card exists, but not with the given set and collector number.
+    #[display("No printing of {name} found in {set} with collector number {collector_number}")]
+    PrintingNotFound {
+        name: String,
+        set: String,
+        collector_number: String,
+    },
+    #[display("Card not found: {name}, did you mean {suggestion}?")]
+    Misspelled { name: String, suggestion: String },
+    #[display("Failed to decode image of {card}: {message}")]
+    ImageDecode { card: String, message: String },
+    /// Reading a decklist, collection or snapshot failed.
+    #[display("Failed to read {path}: {message}")]
+    FileRead { path: String, message: String },
+    // Not a `#[source]`, the message already is the whole error
+    #[display("{_0}")]
+    Io(std::io::Error),
+    #[display("Failed to write PDF {path}: {message}")]
+    PdfWrite { path: String, message: String },
+    /// Rendering into a writer failed, see `Deck::render`.
+    #[display("Failed to render PDF: {_0}")]
+    PdfRender(String),
+    #[display("Neither XDG_CACHE_HOME nor HOME is set, pass a cache directory explicitly")]
+    NoCacheDir,
+    #[display("Invalid config: {_0}")]
+    Config(String),
+    #[display("Background task failed: {_0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+impl Error {
+    pub(crate) fn parse(input: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Error::SourceParse {
+            input: input.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn read(path: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Error::FileRead {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn network(url: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Error::Network {
+            url: url.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A card left out of a print because it couldn't be resolved or downloaded,
+/// collected instead of failing when `DeckGenerationOptions::tolerant` is set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Failure {
+    /// Card name, or the decklist line if it couldn't be resolved.
+    pub card: String,
+    pub quantity: u32,
+    pub stage: FailureStage,
+    pub error: String,
+}
+
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureStage {
+    #[display("resolve")]
+    Resolve,
+    #[display("download")]
+    Download,
+}
@@ -0,0 +1,83 @@
+use serde::Serialize;
+
+#[cfg(feature = "config")]
+use crate::config::Config;
+use crate::error::Failure;
+use crate::listing::DeckListing;
+
+/// Something tiffer did, passed to `ProgressReporter::event` so that other
+/// programs can follow along, e.g. as one line of JSON with `--output json`.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ResolveStarted {
+        source: String,
+    },
+    CardResolved {
+        name: String,
+        scryfall_id: String,
+        quantity: u32,
+    },
+    /// A misspelled name was replaced by the card Scryfall matched it to.
+    CardCorrected {
+        original: String,
+        corrected: String,
+    },
+    DeckResolved {
+        name: String,
+        cards: usize,
+        tokens: usize,
+    },
+    /// One more item of a stage such as "download" or "render" is done.
+    Progress {
+        stage: &'static str,
+        item: String,
+        done: u64,
+        total: u64,
+    },
+    PageRendered {
+        page: usize,
+    },
+    Listing(DeckListing),
+    Warning {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+    Finished {
+        /// The PDF written, if any.
+        path: Option<String>,
+        cards: usize,
+        tokens: usize,
+        pages: usize,
+        /// Copies of cards left out in tolerant mode.
+        failed: usize,
+    },
+    /// A card left out in tolerant mode, reported once everything else is done.
+    CardFailed(Failure),
+    CachePaths {
+        dir: String,
+        /// The read-only cache, if one is set.
+        shared_dir: Option<String>,
+    },
+    CacheStats {
+        images: usize,
+        /// In bytes.
+        size: u64,
+        /// When the least recently used image was last used, in RFC 3339.
+        oldest: Option<String>,
+    },
+    /// Images removed by cleaning, verifying or pruning the cache.
+    CacheCleaned {
+        removed: usize,
+        /// In bytes.
+        size: u64,
+    },
+    /// The settings in effect and the config files they were read from.
+    #[cfg(feature = "config")]
+    ConfigShown {
+        files: Vec<String>,
+        config: Config,
+    },
+}
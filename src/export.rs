@@ -0,0 +1,444 @@
+#[cfg(feature = "csv")]
+use std::io;
+
+use crate::deck::{Card, Deck, Finish};
+use crate::error::Result;
+
+/// Deck formats of other tools a resolved deck can be converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ExportFormat {
+    /// MTG Arena import text
+    Arena,
+    /// Magic Online .dek
+    Mtgo,
+    /// Moxfield import text
+    Moxfield,
+    /// One row per printing with its set, collector number and finish
+    #[cfg(feature = "csv")]
+    Csv,
+    /// Cockatrice .cod
+    Cockatrice,
+}
+
+impl ExportFormat {
+    /// File extension, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Arena | ExportFormat::Moxfield => "txt",
+            ExportFormat::Mtgo => "dek",
+            #[cfg(feature = "csv")]
+            ExportFormat::Csv => "csv",
+            ExportFormat::Cockatrice => "cod",
+        }
+    }
+
+    /// Whether the format keeps cards that are only being considered.
+    fn has_maybeboard(&self) -> bool {
+        match self {
+            ExportFormat::Moxfield => true,
+            #[cfg(feature = "csv")]
+            ExportFormat::Csv => true,
+            _ => false,
+        }
+    }
+}
+
+/// Copies of one printing within a section, tokens and back faces left out.
+struct Entry<'a> {
+    card: &'a Card,
+    quantity: u32,
+}
+
+/// Sections in the order decklists put them, main deck as `None`.
+const SECTIONS: [Option<&str>; 5] = [
+    Some("commander"),
+    Some("companion"),
+    None,
+    Some("sideboard"),
+    Some("maybeboard"),
+];
+
+impl Deck {
+    /// Writes the deck in another tool's format. Tokens are left out, the
+    /// tools add them themselves, and so is the maybeboard for tools that
+    /// have no place for it.
+    pub fn export(&self, format: ExportFormat) -> Result<String> {
+        let mut sections = self.sections();
+        if !format.has_maybeboard() {
+            sections.retain(|(section, _)| *section != Some("maybeboard"));
+        }
+        let text = match format {
+            ExportFormat::Arena => text(&sections, arena_header, arena_line),
+            ExportFormat::Moxfield => {
+                // Lines before the first header belong to the main deck
+                sections.sort_by_key(|(section, _)| section.is_some());
+                text(&sections, moxfield_header, moxfield_line)
+            }
+            ExportFormat::Mtgo => mtgo(&sections),
+            #[cfg(feature = "csv")]
+            ExportFormat::Csv => csv(&sections)?,
+            ExportFormat::Cockatrice => cockatrice(&self.name, &sections),
+        };
+
+        Ok(text)
+    }
+
+    /// Groups the copies of every printing by section, in decklist order.
+    fn sections(&self) -> Vec<(Option<&str>, Vec<Entry<'_>>)> {
+        let unknown = self
+            .cards
+            .iter()
+            .filter_map(|card| card.section.as_deref())
+            .filter(|section| !SECTIONS.contains(&Some(section)));
+
+        let mut order: Vec<Option<&str>> = SECTIONS.to_vec();
+        for section in unknown {
+            if !order.contains(&Some(section)) {
+                order.push(Some(section));
+            }
+        }
+
+        order
+            .into_iter()
+            .map(|section| {
+                let mut entries: Vec<Entry> = Vec::new();
+                let cards = self
+                    .cards
+                    .iter()
+                    .filter(|card| !card.backface && card.section.as_deref() == section);
+
+                for card in cards {
+                    let existing = entries.iter_mut().find(|entry| {
+                        entry.card.scryfall_id == card.scryfall_id
+                            && entry.card.finish == card.finish
+                    });
+                    match existing {
+                        Some(entry) => entry.quantity += card.quantity,
+                        None => entries.push(Entry {
+                            card,
+                            quantity: card.quantity,
+                        }),
+                    }
+                }
+
+                (section, entries)
+            })
+            .filter(|(_, entries)| !entries.is_empty())
+            .collect()
+    }
+}
+
+/// The name other tools know a card by: the front face of cards with two
+/// faces, the full name of split cards.
+fn display_name(card: &Card) -> &str {
+    match card.layout.as_deref() {
+        Some("transform" | "modal_dfc" | "adventure" | "flip" | "meld") => {
+            card.name.split(" // ").next().unwrap_or(&card.name)
+        }
+        _ => &card.name,
+    }
+}
+
+fn printing(card: &Card) -> String {
+    match (&card.set, &card.collector_number) {
+        (Some(set), Some(number)) => format!(" ({}) {}", set.to_uppercase(), number),
+        (Some(set), None) => format!(" ({})", set.to_uppercase()),
+        _ => String::new(),
+    }
+}
+
+/// Plain text, sections separated by a blank line and their header if any.
+fn text(
+    sections: &[(Option<&str>, Vec<Entry>)],
+    header: fn(Option<&str>) -> Option<String>,
+    line: fn(&Entry) -> String,
+) -> String {
+    let mut out = String::new();
+    for (i, (section, entries)) in sections.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if let Some(header) = header(*section) {
+            out.push_str(&header);
+            out.push('\n');
+        }
+        for entry in entries {
+            out.push_str(&line(entry));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn capitalize(section: &str) -> String {
+    let mut chars = section.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn arena_header(section: Option<&str>) -> Option<String> {
+    Some(capitalize(section.unwrap_or("deck")))
+}
+
+// e.g. 4 Delver of Secrets (MID) 47
+fn arena_line(entry: &Entry) -> String {
+    format!(
+        "{} {}{}",
+        entry.quantity,
+        display_name(entry.card),
+        printing(entry.card)
+    )
+}
+
+/// The main deck goes first, without a header.
+fn moxfield_header(section: Option<&str>) -> Option<String> {
+    section.map(|section| format!("{}:", section.to_uppercase()))
+}
+
+// e.g. 1 Whiptongue Hydra (NEC) 134 *F*
+fn moxfield_line(entry: &Entry) -> String {
+    let finish = match entry.card.finish {
+        Some(Finish::Foil) => " *F*",
+        Some(Finish::Etched) => " *E*",
+        _ => "",
+    };
+    format!(
+        "{} {}{}{}",
+        entry.quantity,
+        entry.card.name,
+        printing(entry.card),
+        finish
+    )
+}
+
+/// Formats without a companion zone keep it in the sideboard, where it
+/// starts the game.
+fn is_sideboard(section: Option<&str>) -> bool {
+    matches!(section, Some("sideboard" | "companion"))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn mtgo(sections: &[(Option<&str>, Vec<Entry>)]) -> String {
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<Deck xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" ",
+        "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+        "  <NetDeckID>0</NetDeckID>\n",
+        "  <PreconstructedDeckID>0</PreconstructedDeckID>\n",
+    ));
+    for (section, entries) in sections {
+        for entry in entries {
+            out.push_str(&format!(
+                "  <Cards Quantity=\"{}\" Sideboard=\"{}\" Name=\"{}\" />\n",
+                entry.quantity,
+                is_sideboard(*section),
+                xml_escape(display_name(entry.card))
+            ));
+        }
+    }
+    out.push_str("</Deck>\n");
+    out
+}
+
+#[cfg(feature = "csv")]
+fn csv(sections: &[(Option<&str>, Vec<Entry>)]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let rows = sections.iter().flat_map(|(section, entries)| {
+        entries.iter().map(move |entry| {
+            let card = entry.card;
+            [
+                entry.quantity.to_string(),
+                card.name.clone(),
+                card.set.clone().unwrap_or_default(),
+                card.collector_number.clone().unwrap_or_default(),
+                card.lang.clone().unwrap_or_else(|| "en".to_string()),
+                card.finish.unwrap_or(Finish::Nonfoil).to_string(),
+                section.unwrap_or("mainboard").to_string(),
+                card.scryfall_id.clone(),
+            ]
+        })
+    });
+
+    let header = [
+        "Count",
+        "Name",
+        "Edition",
+        "Collector Number",
+        "Language",
+        "Finish",
+        "Section",
+        "Scryfall ID",
+    ];
+    writer.write_record(header).map_err(io::Error::other)?;
+    for row in rows {
+        writer.write_record(&row).map_err(io::Error::other)?;
+    }
+
+    let data = writer
+        .into_inner()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn cockatrice(name: &str, sections: &[(Option<&str>, Vec<Entry>)]) -> String {
+    let mut out = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<cockatrice_deck version=\"1\">\n",
+            "    <deckname>{}</deckname>\n",
+            "    <comments></comments>\n",
+        ),
+        xml_escape(name)
+    );
+    for (zone, sideboard) in [("main", false), ("side", true)] {
+        let entries: Vec<&Entry> = sections
+            .iter()
+            .filter(|(section, _)| is_sideboard(*section) == sideboard)
+            .flat_map(|(_, entries)| entries)
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+
+        out.push_str(&format!("    <zone name=\"{}\">\n", zone));
+        for entry in entries {
+            out.push_str(&format!(
+                "        <card number=\"{}\" name=\"{}\"/>\n",
+                entry.quantity,
+                xml_escape(display_name(entry.card))
+            ));
+        }
+        out.push_str("    </zone>\n");
+    }
+    out.push_str("</cockatrice_deck>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// A card printed in NEC, in the given section.
+    fn card(name: &str, id: &str, quantity: u32, section: Option<&str>) -> Card {
+        Card {
+            set: Some("nec".to_string()),
+            collector_number: Some("134".to_string()),
+            section: section.map(str::to_string),
+            ..testing::card(name, id, quantity)
+        }
+    }
+
+    fn deck() -> Deck {
+        let delver = Card {
+            layout: Some("transform".to_string()),
+            ..card("Delver of Secrets // Insectile Aberration", "d", 2, None)
+        };
+        let delver_back = testing::back_of(&delver);
+        let foil = Card {
+            finish: Some(Finish::Foil),
+            ..card("Opt", "o", 1, Some("sideboard"))
+        };
+
+        Deck::new(
+            "Tempo & Co",
+            vec![
+                delver,
+                delver_back,
+                card("Island", "i", 3, None),
+                card("Island", "i", 2, None),
+                card("Rock & Roll", "r", 1, Some("commander")),
+                foil,
+                card("Ponder", "p", 1, Some("maybeboard")),
+            ],
+            vec![card("Treasure", "t", 1, None)],
+        )
+    }
+
+    #[test]
+    fn arena() {
+        assert_eq!(
+            deck().export(ExportFormat::Arena).unwrap(),
+            "Commander\n1 Rock & Roll (NEC) 134\n\n\
+             Deck\n2 Delver of Secrets (NEC) 134\n5 Island (NEC) 134\n\n\
+             Sideboard\n1 Opt (NEC) 134\n"
+        );
+    }
+
+    #[test]
+    fn moxfield() {
+        assert_eq!(
+            deck().export(ExportFormat::Moxfield).unwrap(),
+            "2 Delver of Secrets // Insectile Aberration (NEC) 134\n5 Island (NEC) 134\n\n\
+             COMMANDER:\n1 Rock & Roll (NEC) 134\n\n\
+             SIDEBOARD:\n1 Opt (NEC) 134 *F*\n\n\
+             MAYBEBOARD:\n1 Ponder (NEC) 134\n"
+        );
+    }
+
+    #[test]
+    fn mtgo() {
+        let dek = deck().export(ExportFormat::Mtgo).unwrap();
+
+        assert!(dek.contains(r#"<Cards Quantity="1" Sideboard="false" Name="Rock &amp; Roll" />"#));
+        assert!(
+            dek.contains(r#"<Cards Quantity="2" Sideboard="false" Name="Delver of Secrets" />"#)
+        );
+        assert!(dek.contains(r#"<Cards Quantity="1" Sideboard="true" Name="Opt" />"#));
+        assert!(!dek.contains("Ponder"));
+        assert!(!dek.contains("Treasure"));
+    }
+
+    #[test]
+    fn mtgo_companion_in_sideboard() {
+        let deck = Deck::new(
+            "Deck",
+            vec![
+                card("Opt", "o", 4, None),
+                card("Lurrus of the Dream-Den", "l", 1, Some("companion")),
+            ],
+            Vec::new(),
+        );
+        let dek = deck.export(ExportFormat::Mtgo).unwrap();
+
+        assert!(dek
+            .contains(r#"<Cards Quantity="1" Sideboard="true" Name="Lurrus of the Dream-Den" />"#));
+    }
+
+    #[test]
+    fn cockatrice() {
+        let cod = deck().export(ExportFormat::Cockatrice).unwrap();
+
+        assert!(cod.contains("<deckname>Tempo &amp; Co</deckname>"));
+        assert!(cod.contains(
+            "<zone name=\"side\">\n        <card number=\"1\" name=\"Opt\"/>\n    </zone>"
+        ));
+        assert!(!cod.contains("Ponder"));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv() {
+        let csv = deck().export(ExportFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "Count,Name,Edition,Collector Number,Language,Finish,Section,Scryfall ID"
+        );
+        assert!(lines.contains(&"1,Opt,nec,134,en,foil,sideboard,o"));
+        assert!(lines.contains(&"1,Ponder,nec,134,en,nonfoil,maybeboard,p"));
+        assert!(lines.contains(&"5,Island,nec,134,en,nonfoil,mainboard,i"));
+        assert_eq!(lines.len(), 6);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tokio::task;
+
+use crate::deck::Deck;
+use crate::error::Result;
+use crate::progress::{Progress, Reporter};
+use crate::scryfall::ScryfallClient;
+
+#[derive(Debug, Deserialize)]
+struct ScryfallPrinting {
+    id: String,
+    name: String,
+    set: String,
+    collector_number: String,
+    lang: String,
+    #[serde(default)]
+    image_status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScryfallList {
+    data: Vec<ScryfallPrinting>,
+}
+
+impl Deck {
+    /// Swaps every card for a printing in the given language, keeping the
+    /// English printing for cards that were never printed in it. When
+    /// `tolerant`, cards that can't be looked up keep their printing too.
+    pub async fn localize(
+        &mut self,
+        lang: &str,
+        tolerant: bool,
+        reporter: &Reporter,
+    ) -> Result<()> {
+        let mut ids: Vec<String> = self
+            .cards
+            .iter()
+            .chain(self.tokens.iter())
+            .map(|card| card.scryfall_id.clone())
+            .collect();
+        ids.sort();
+        ids.dedup();
+
+        reporter.status(&format!("Resolving {} printings...", lang));
+        let progress = Progress::new(reporter, "localize", ids.len() as u64);
+
+        let tasks: Vec<_> = ids
+            .into_iter()
+            .map(|id| {
+                let lang = lang.to_string();
+                let progress = progress.clone();
+                task::spawn(async move {
+                    let printing = get_localized_printing(&id, &lang).await;
+                    progress.inc(&id);
+                    (id, printing)
+                })
+            })
+            .collect();
+
+        let mut printings = HashMap::new();
+        for task in tasks {
+            match task.await? {
+                (id, Ok(printing)) => {
+                    printings.insert(id, printing);
+                }
+                (id, Err(e)) if tolerant => {
+                    let cards = self.cards.iter().chain(self.tokens.iter());
+                    let name = cards
+                        .filter(|card| card.scryfall_id == id)
+                        .map(|card| card.name.as_str())
+                        .next()
+                        .unwrap_or(&id);
+                    log::warn!("{}, keeping the printing of {}", e, name);
+                }
+                (_, Err(e)) => return Err(e),
+            }
+        }
+        progress.finish();
+
+        for card in self.cards.iter_mut().chain(self.tokens.iter_mut()) {
+            match printings.get(&card.scryfall_id) {
+                Some(Some(printing)) => {
+                    card.scryfall_id = printing.id.clone();
+                    card.lang = Some(printing.lang.clone());
+                    card.set = Some(printing.set.clone());
+                    card.collector_number = Some(printing.collector_number.clone());
+                    card.image_status = printing.image_status.clone();
+                }
+                _ => {
+                    if !card.backface {
+                        log::info!("No {} printing of {}, using English", lang, card.name);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Looks up the same printing in another language first, then any printing
+/// of the card in that language.
+async fn get_localized_printing(id: &str, lang: &str) -> Result<Option<ScryfallPrinting>> {
+    let Some(card) = ScryfallClient::shared()?
+        .get_json::<ScryfallPrinting>(&format!("https://api.scryfall.com/cards/{}", id), &[])
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    if card.lang == lang {
+        return Ok(Some(card));
+    }
+
+    let same_printing = ScryfallClient::shared()?
+        .get_json::<ScryfallPrinting>(
+            &format!(
+                "https://api.scryfall.com/cards/{}/{}/{}",
+                card.set, card.collector_number, lang
+            ),
+            &[],
+        )
+        .await?;
+    if same_printing.is_some() {
+        return Ok(same_printing);
+    }
+
+    let other_printings = ScryfallClient::shared()?
+        .get_json::<ScryfallList>(
+            "https://api.scryfall.com/cards/search",
+            &[
+                ("q", &format!("!\"{}\" lang:{}", card.name, lang)),
+                ("unique", "prints"),
+            ],
+        )
+        .await?;
+
+    Ok(other_printings.and_then(|list| list.data.into_iter().next()))
+}
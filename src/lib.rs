@@ -1,4 +1,28 @@
+pub mod error;
 pub mod source;
+#[cfg(feature = "remote")]
 pub mod remote;
 pub mod local;
 pub mod deck;
+#[cfg(feature = "pdf")]
+pub mod render;
+pub mod diff;
+pub mod export;
+pub mod listing;
+pub mod snapshot;
+pub mod collection;
+pub mod language;
+pub mod printing;
+pub mod scryfall;
+pub mod cache;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod events;
+pub mod progress;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+#[cfg(test)]
+mod testing;
+
+pub use error::{Error, Result};
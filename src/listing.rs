@@ -0,0 +1,82 @@
+use serde::Serialize;
+
+use crate::deck::{Card, Deck, Finish};
+
+/// A resolved deck as it would be printed, with copies of the same printing grouped.
+#[derive(Debug, Serialize, Clone)]
+pub struct DeckListing {
+    pub name: String,
+    pub entries: Vec<ListingEntry>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ListingEntry {
+    /// Name of the deck the cards come from, which differs between merged
+    /// decks, followed by the decklist section if any, e.g. "Deck / sideboard".
+    pub section: String,
+    pub quantity: usize,
+    pub name: String,
+    pub set: Option<String>,
+    pub collector_number: Option<String>,
+    pub scryfall_id: String,
+    pub lang: Option<String>,
+    pub finish: Option<Finish>,
+    pub token: bool,
+    pub backface: bool,
+}
+
+impl Deck {
+    /// Lists every printing in the deck, section by section, cards before tokens.
+    pub fn listing(&self) -> DeckListing {
+        let mut entries = Vec::new();
+        for part in 0..self.parts.len().max(1) {
+            let section = self.parts.get(part).unwrap_or(&self.name);
+            add_entries(&mut entries, section, part, &self.cards, false);
+            add_entries(&mut entries, section, part, &self.tokens, true);
+        }
+
+        DeckListing {
+            name: self.name.clone(),
+            entries,
+        }
+    }
+}
+
+fn add_entries(
+    entries: &mut Vec<ListingEntry>,
+    section: &str,
+    part: usize,
+    cards: &[Card],
+    token: bool,
+) {
+    let start = entries.len();
+
+    for card in cards.iter().filter(|card| card.part == part) {
+        let section = match &card.section {
+            Some(card_section) => format!("{} / {}", section, card_section),
+            None => section.to_string(),
+        };
+        let existing = entries[start..].iter_mut().find(|entry| {
+            entry.scryfall_id == card.scryfall_id
+                && entry.backface == card.backface
+                && entry.finish == card.finish
+                && entry.section == section
+        });
+
+        match existing {
+            Some(entry) => entry.quantity += card.quantity as usize,
+            None => entries.push(ListingEntry {
+                section,
+                quantity: card.quantity as usize,
+                name: card.name.clone(),
+                set: card.set.clone(),
+                collector_number: card.collector_number.clone(),
+                scryfall_id: card.scryfall_id.clone(),
+                lang: card.lang.clone(),
+                finish: card.finish,
+                token,
+                backface: card.backface,
+            }),
+        }
+    }
+}
@@ -1,38 +1,70 @@
-use std::collections::HashMap;
 use std::io::prelude::*;
-use std::time::Duration;
 use std::{fs::File, io::BufReader, path::PathBuf};
-use indicatif::ProgressBar;
-use reqwest::header::HeaderMap;
+use tokio::sync::Mutex;
 use tokio::task;
 
-use serde::Deserialize;
-use tokio::time::sleep;
+use serde::{Deserialize, Serialize};
 
-use crate::deck::{Card, Deck};
+use crate::deck::{Card, Deck, DeckGenerationOptions, Finish};
+use crate::error::{Error, Failure, FailureStage, Result};
+use crate::events::Event;
+use crate::progress::{Progress, Reporter};
+use crate::scryfall::ScryfallClient;
 
-pub async fn get_local_deck(path: PathBuf) -> anyhow::Result<Deck> {
-    let file = File::open(path)?;
+/// What to do when a card name has no exact match on Scryfall but a close one.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FuzzyMatch {
+    /// Fail, without looking for a close match.
+    Off,
+    /// Fail, naming the close match in the error.
+    Suggest,
+    /// Ask `ProgressReporter::confirm_correction` whether to use the close
+    /// match, suggesting it when declined.
+    #[default]
+    Ask,
+    /// Use the close match.
+    Accept,
+}
+
+/// Reads a decklist and looks up every card on Scryfall. With
+/// `options.tolerant`, lines that can't be resolved end up in
+/// `Deck::failures` instead of failing the whole import.
+pub async fn get_local_deck(path: PathBuf, options: &DeckGenerationOptions) -> Result<Deck> {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "deck".to_string());
+
+    let file = File::open(&path).map_err(|e| Error::read(path.display(), e))?;
     let reader = BufReader::new(file);
 
-    // Collect lines into a Vec
+    // Collect lines into a Vec, along with the section they are in
     let mut lines = Vec::new();
+    let mut section = None;
     for line in reader.lines() {
-        lines.push(line?);
+        let line = line.map_err(|e| Error::read(path.display(), e))?;
+        if let Some(header) = section_header(&line) {
+            section = header;
+        } else if !line.trim().is_empty() {
+            lines.push((line, section.clone()));
+        }
     }
 
     // Create a progress bar
-    let bar = ProgressBar::new(lines.len() as u64);
+    let progress = Progress::new(&options.reporter, "resolve", lines.len() as u64);
 
     // Create a vector of tasks
     let tasks: Vec<_> = lines
-        .into_iter()
-        .map(|line| {
-            let line = line.clone();
-            let bar = bar.clone();
-            task::spawn(async move { 
-                let parse = parse_card(&line).await;
-                bar.inc(1);
+        .iter()
+        .map(|(line, section)| {
+            let (line, section) = (line.clone(), section.clone());
+            let progress = progress.clone();
+            let fuzzy = options.fuzzy;
+            let reporter = options.reporter.clone();
+            task::spawn(async move {
+                let parse = parse_card(&line, section, fuzzy, &reporter).await;
+                progress.inc(&line);
                 parse
             })
         })
@@ -41,153 +73,292 @@ pub async fn get_local_deck(path: PathBuf) -> anyhow::Result<Deck> {
     // Collect results from tasks
     let mut cards = Vec::new();
     let mut tokens = Vec::new();
+    let mut failures = Vec::new();
 
-    for task in tasks {
-        let (parsed_cards, parsed_tokens) = task.await??;
-        cards.extend(parsed_cards);
-        tokens.extend(parsed_tokens);
+    for ((line, _), task) in lines.iter().zip(tasks) {
+        match task.await? {
+            Ok((parsed_cards, parsed_tokens)) => {
+                cards.extend(parsed_cards);
+                tokens.extend(parsed_tokens);
+            }
+            Err(e) if options.tolerant => failures.push(Failure {
+                card: line.trim().to_string(),
+                quantity: line
+                    .split_whitespace()
+                    .next()
+                    .and_then(|quantity| quantity.parse().ok())
+                    .unwrap_or(1),
+                stage: FailureStage::Resolve,
+                error: e.to_string(),
+            }),
+            Err(e) => return Err(e),
+        }
     }
+    progress.finish();
 
     Ok(Deck {
-        name: "deck".to_string(),
-        cards,
-        tokens,
+        failures,
+        ..Deck::new(name, cards, tokens)
     })
 }
 
 #[derive(Debug, Deserialize)]
-struct ScryfallCard {
-    name: String,
-    id: String,
+pub(crate) struct ScryfallCard {
+    pub(crate) name: String,
+    pub(crate) id: String,
+    pub(crate) set: String,
+    pub(crate) collector_number: String,
+    #[serde(default)]
+    lang: String,
+    #[serde(default)]
+    layout: String,
+    #[serde(default)]
+    type_line: Option<String>,
+    #[serde(default)]
+    image_status: Option<String>,
     all_parts: Option<Vec<ScryfallRelatedCard>>,
     card_faces: Option<Vec<ScryfallCardFace>>,
 }
 
 #[derive(Debug, Deserialize)]
-struct ScryfallCardFace {
-}
+struct ScryfallCardFace {}
 
 #[derive(Debug, Deserialize)]
 struct ScryfallRelatedCard {
     name: String,
     component: String,
     id: String,
+    #[serde(default)]
+    type_line: Option<String>,
 }
 
-// example card: 1 Whiptongue Hydra (NEC) 134
-async fn parse_card(line: &str) -> anyhow::Result<(Vec<Card>, Vec<Card>)> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    let quantity = parts[0].parse::<u32>()?;
+/// The section a decklist line starts, e.g. "Sideboard" or "// Commander" as
+/// written by MTG Arena and Moxfield. `Some(None)` is the main deck.
+pub(crate) fn section_header(line: &str) -> Option<Option<String>> {
+    let header = line
+        .trim()
+        .trim_start_matches("//")
+        .trim()
+        .trim_end_matches(':')
+        .to_lowercase();
+
+    match header.as_str() {
+        "deck" | "main" | "mainboard" => Some(None),
+        "sideboard" | "commander" | "companion" | "maybeboard" => Some(Some(header)),
+        _ => None,
+    }
+}
+
+// example card: 1 Whiptongue Hydra (NEC) 134, optionally followed by *F* for foil
+async fn parse_card(
+    line: &str,
+    section: Option<String>,
+    fuzzy: FuzzyMatch,
+    reporter: &Reporter,
+) -> Result<(Vec<Card>, Vec<Card>)> {
+    let mut parts: Vec<&str> = line.split_whitespace().collect();
+    let finish = match parts.last() {
+        Some(&"*F*") => Some(Finish::Foil),
+        Some(&"*E*") => Some(Finish::Etched),
+        _ => None,
+    };
+    if finish.is_some() {
+        parts.pop();
+    }
+    if parts.len() < 4 {
+        return Err(Error::parse(
+            line,
+            "expected a quantity, name, set and collector number",
+        ));
+    }
+
+    let quantity = parts[0]
+        .parse::<u32>()
+        .map_err(|e| Error::parse(line, format!("invalid quantity: {}", e)))?;
     let name = parts[1..parts.len() - 2].join(" ");
     let set = parts[parts.len() - 2].trim_matches(|c: char| !c.is_alphabetic());
     let collector_number = parts[parts.len() - 1];
 
     log::debug!("{} {} {} {}", quantity, name, set, collector_number);
 
-    let details = match get_card_details(&name, set, collector_number).await {
-        Ok(details) => details,
+    let (details, pinned) = match find_card(&name, set, collector_number, fuzzy, reporter).await {
+        Ok(found) => found,
         Err(e) => {
-            eprintln!("Failed to get card details for {}: {}", name, e);
+            reporter.warning(&format!("Failed to get card details for {}: {}", name, e));
             return Err(e);
         }
     };
 
-    let mut cards = Vec::new();
-    let mut tokens = Vec::new();
-    for _ in 0..quantity {
-        let card = Card {
-            name: details.name.clone(),
-            scryfall_id: details.id.clone(),
-            backface: false,
-        };
-
-        if details.card_faces.is_some() {
-            cards.push(Card {
-                name: details.name.clone(),
-                scryfall_id: details.id.clone(),
-                backface: true,
-            });
-        }
+    reporter.event(&Event::CardResolved {
+        name: details.name.clone(),
+        scryfall_id: details.id.clone(),
+        quantity,
+    });
 
-        if let Some(details) = &details.all_parts {
-            for related in details {
-                if related.component == "token" {
-                    let token = Card {
-                        name: related.name.clone(),
-                        scryfall_id: related.id.clone(),
-                        backface: false,
-                    };
-                    tokens.push(token);
-                }
-            }
-        }
+    let card = Card {
+        name: details.name.clone(),
+        scryfall_id: details.id.clone(),
+        quantity,
+        lang: (!details.lang.is_empty() && details.lang != "en").then(|| details.lang.clone()),
+        pinned,
+        set: Some(details.set.clone()),
+        collector_number: Some(details.collector_number.clone()),
+        finish,
+        layout: Some(details.layout.clone()).filter(|layout| !layout.is_empty()),
+        type_line: details.type_line.clone(),
+        section: section.clone(),
+        image_status: details.image_status.clone(),
+        ..Card::default()
+    };
 
-        cards.push(card);
+    let mut cards = vec![card.clone()];
+    if details.card_faces.is_some() {
+        cards.push(Card {
+            backface: true,
+            ..card
+        });
     }
 
+    let tokens = details
+        .all_parts
+        .iter()
+        .flatten()
+        .filter(|related| related.component == "token")
+        .map(|related| Card {
+            name: related.name.clone(),
+            scryfall_id: related.id.clone(),
+            quantity,
+            type_line: related.type_line.clone(),
+            section: section.clone(),
+            ..Card::default()
+        })
+        .collect();
+
     Ok((cards, tokens))
 }
 
-async fn get_card_details(
+/// Looks up a card by its exact name, falling back to Scryfall's fuzzy
+/// matching and autocompletion when the name is misspelled. Also tells
+/// whether the card is the printing the decklist asked for.
+async fn find_card(
     name: &str,
     set: &str,
     collector_number: &str,
-) -> anyhow::Result<ScryfallCard> {
-    const MAX_RETRIES: u32 = 5;
-    const INITIAL_BACKOFF_SECS: u64 = 1;
-
-    let client = reqwest::Client::builder()
-        .user_agent("curl/7.68.0")
-        .default_headers({
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                reqwest::header::ACCEPT,
-                reqwest::header::HeaderValue::from_static("application/json"),
-            );
-            headers
-        })
-        .build()?;
-
-    let mut attempt = 0;
-
-    while attempt < MAX_RETRIES {
-        let resp = client
-            .get("https://api.scryfall.com/cards/named")
-            .query(&[
-                ("exact", name),
-                ("set", set),
-                ("collector_number", collector_number),
-            ])
-            .send()
-            .await?;
-
-        if resp.status().is_success() {
-            let card: ScryfallCard = resp
-                .json()
-                .await?;
-            return Ok(card);
-        } else if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            // Extract the retry-after duration from the headers if available
-            if let Some(retry_after_header) = resp.headers().get("Retry-After") {
-                if let Ok(retry_after) = retry_after_header.to_str().unwrap().parse::<u64>() {
-                    let backoff_duration = Duration::from_secs(retry_after);
-                    eprintln!("Rate limit exceeded. Retrying after {} seconds...", retry_after);
-                    sleep(backoff_duration).await;
-                } else {
-                    eprintln!("Rate limit exceeded but no valid Retry-After header provided.");
-                    // Use a default backoff duration if Retry-After header is invalid
-                    sleep(Duration::from_secs(INITIAL_BACKOFF_SECS)).await;
-                }
-            } else {
-                eprintln!("Rate limit exceeded but no Retry-After header found. Using default backoff.");
-                sleep(Duration::from_secs(INITIAL_BACKOFF_SECS)).await;
-            }
-        } else {
-            return Err(anyhow::anyhow!("Failed to get card details: {}", resp.status()));
+    fuzzy: FuzzyMatch,
+    reporter: &Reporter,
+) -> Result<(ScryfallCard, bool)> {
+    let exact = get_card_details(name, set, collector_number).await;
+    if fuzzy == FuzzyMatch::Off || !matches!(exact, Err(Error::CardNotFound(_))) {
+        return exact.map(|card| (card, true));
+    }
+
+    let Some(corrected) = suggest_name(name, set).await? else {
+        return exact.map(|card| (card, true));
+    };
+    // Only the set or collector number is wrong, nothing to correct
+    if is_same_name(name, &corrected) {
+        return Err(Error::PrintingNotFound {
+            name: corrected,
+            set: set.to_string(),
+            collector_number: collector_number.to_string(),
+        });
+    }
+
+    let accepted = match fuzzy {
+        FuzzyMatch::Accept => true,
+        FuzzyMatch::Ask => confirm_correction(name, &corrected, reporter).await,
+        _ => false,
+    };
+    if !accepted {
+        return Err(Error::Misspelled {
+            name: name.to_string(),
+            suggestion: corrected,
+        });
+    }
+
+    reporter.status(&format!("Using \"{}\" for \"{}\"", corrected, name));
+    reporter.event(&Event::CardCorrected {
+        original: name.to_string(),
+        corrected: corrected.clone(),
+    });
+
+    // The set and collector number may have been as wrong as the name, then
+    // Scryfall's default printing is as good as any
+    match get_card_details(&corrected, set, collector_number).await {
+        Err(Error::CardNotFound(_)) => get_named_card(&[("exact", &corrected)])
+            .await?
+            .map(|card| (card, false))
+            .ok_or(Error::CardNotFound(corrected)),
+        result => result.map(|card| (card, true)),
+    }
+}
+
+/// Whether a suggested name is the one given, which may be just the front
+/// face of a double-faced card.
+fn is_same_name(name: &str, suggested: &str) -> bool {
+    let name = name.trim();
+    suggested.eq_ignore_ascii_case(name)
+        || suggested
+            .split(" // ")
+            .next()
+            .is_some_and(|front| front.eq_ignore_ascii_case(name))
+}
+
+/// The card Scryfall thinks a misspelled name refers to, if any.
+async fn suggest_name(name: &str, set: &str) -> Result<Option<String>> {
+    for query in [vec![("fuzzy", name), ("set", set)], vec![("fuzzy", name)]] {
+        if let Some(card) = get_named_card(&query).await? {
+            return Ok(Some(card.name));
         }
+    }
 
-        attempt += 1;
+    #[derive(Debug, Deserialize)]
+    struct ScryfallCatalog {
+        data: Vec<String>,
     }
 
-    Err(anyhow::anyhow!("Exceeded maximum number of retries"))
+    // Fuzzy matching gives up when several cards match, autocomplete lists them
+    let catalog = ScryfallClient::shared()?
+        .get_json::<ScryfallCatalog>(
+            "https://api.scryfall.com/cards/autocomplete",
+            &[("q", name)],
+        )
+        .await?;
+
+    Ok(catalog.and_then(|catalog| catalog.data.into_iter().next()))
+}
+
+/// Asks the reporter whether to use a corrected name.
+async fn confirm_correction(name: &str, corrected: &str, reporter: &Reporter) -> bool {
+    // One question at a time, cards are looked up concurrently
+    static PROMPT: Mutex<()> = Mutex::const_new(());
+
+    let _prompt = PROMPT.lock().await;
+    let (name, corrected) = (name.to_string(), corrected.to_string());
+    let reporter = reporter.clone();
+
+    // Answering may take a while, e.g. when a person is asked
+    task::spawn_blocking(move || reporter.confirm_correction(&name, &corrected))
+        .await
+        .unwrap_or(false)
+}
+
+async fn get_named_card(query: &[(&str, &str)]) -> Result<Option<ScryfallCard>> {
+    ScryfallClient::shared()?
+        .get_json("https://api.scryfall.com/cards/named", query)
+        .await
+}
+
+pub(crate) async fn get_card_details(
+    name: &str,
+    set: &str,
+    collector_number: &str,
+) -> Result<ScryfallCard> {
+    get_named_card(&[
+        ("exact", name),
+        ("set", set),
+        ("collector_number", collector_number),
+    ])
+    .await?
+    .ok_or_else(|| Error::CardNotFound(format!("{} ({}) {}", name, set, collector_number)))
 }
@@ -1,40 +1,881 @@
-use clap::Parser;
-use tiffer::deck::Deck;
-use tiffer::local::get_local_deck;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bytesize::ByteSize;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use tiffer::cache::{self, CacheConfig};
+use tiffer::collection::{get_collection, OwnedMatch};
+use tiffer::config::Config;
+use tiffer::deck::{Card, Deck, DeckGenerationOptions, Finish, ImageVersion, PaperSize, Separator};
+use tiffer::diff::DiffKey;
+use tiffer::error::Failure;
+use tiffer::events::Event;
+use tiffer::export::ExportFormat;
+use tiffer::listing::DeckListing;
+use tiffer::local::{get_local_deck, FuzzyMatch};
+use tiffer::printing::PrintingPolicy;
+use tiffer::progress::{JsonReporter, Reporter, TerminalReporter};
 use tiffer::remote::get_remote_deck;
+use tiffer::snapshot::SNAPSHOT_EXTENSION;
 use tiffer::source::Source;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about)]
 struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    cache: CacheArgs,
+
+    /// How to report progress and results
+    #[clap(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Messages and progress bars for people
+    Text,
+    /// One JSON event per line, for programs driving tiffer
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)] // parsed once at startup
+enum Command {
+    /// Generate a printable PDF of one or more decks
+    Print {
+        #[command(flatten)]
+        deck: DeckArgs,
+        #[command(flatten)]
+        download: DownloadArgs,
+        #[command(flatten)]
+        render: RenderArgs,
+        #[command(flatten)]
+        dry_run: DryRunArgs,
+    },
+    /// Download the images of one or more decks into the cache without printing them
+    Fetch {
+        #[command(flatten)]
+        deck: DeckArgs,
+        #[command(flatten)]
+        download: DownloadArgs,
+    },
+    /// Resolve one or more decks and print their card list
+    List {
+        #[command(flatten)]
+        deck: DeckArgs,
+        #[clap(long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+        /// Also save the resolved deck to this file, usable as a source later.
+        /// Name it *.tiffer.json to have it recognized as such
+        #[clap(long)]
+        save: Option<PathBuf>,
+    },
+    /// Convert one or more decks to another tool's deck format
+    Export {
+        #[command(flatten)]
+        deck: DeckArgs,
+        #[clap(long, value_enum)]
+        format: ExportFormat,
+        /// File to write, "-" for standard output [default: <deck name>.<extension>]
+        #[clap(short, long)]
+        filename: Option<String>,
+    },
+    /// Check that every card of the given decks can be found on Scryfall
+    Validate {
+        #[clap(required = true)]
+        sources: Vec<Source>,
+    },
+    /// Print only the cards added between two versions of a deck
+    Diff {
+        /// What makes two cards the same
+        #[clap(short, long, value_enum, default_value_t = DiffKey::Printing)]
+        by: DiffKey,
+        #[command(flatten)]
+        selection: SelectionArgs,
+        #[command(flatten)]
+        download: DownloadArgs,
+        #[command(flatten)]
+        render: RenderArgs,
+        #[command(flatten)]
+        dry_run: DryRunArgs,
+        #[command(flatten)]
+        resolve: ResolveArgs,
+
+        old: Source,
+        new: Source,
+    },
+    /// Inspect and clean up the card image cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+impl Command {
+    /// Whether the result goes to stdout for other programs to read, which
+    /// leaves no room for status messages there.
+    fn writes_stdout(&self) -> bool {
+        match self {
+            Command::List { format, .. } => matches!(format, ListFormat::Json),
+            Command::Print { dry_run, .. } | Command::Diff { dry_run, .. } => {
+                dry_run.dry_run && matches!(dry_run.format, ListFormat::Json)
+            }
+            Command::Export { filename, .. } => filename.as_deref() == Some("-"),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ListFormat {
+    /// Aligned columns for reading
+    Table,
+    /// A JSON object with the deck name and its entries
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Show how many images are cached and how much space they take
+    Stats,
+    /// Remove images that haven't been used for a while
+    Clean {
+        /// e.g. "30days" or "12h"
+        #[clap(long, value_parser = humantime::parse_duration)]
+        older_than: Duration,
+    },
+    /// Decode every image and remove the ones that are corrupt
+    Verify,
+    /// Remove the least recently used images until the cache fits in a size
+    Prune {
+        /// e.g. "500MB" or "2GiB"
+        #[clap(long)]
+        max_size: ByteSize,
+    },
+    /// Print the cache directory
+    Path,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the settings in effect, after applying config files and environment
+    Show,
+}
+
+#[derive(Args, Debug)]
+struct CacheArgs {
+    /// Directory to cache card images in
+    #[clap(long, global = true, env = "TIFFER_CACHE_DIR")]
+    cache_dir: Option<String>,
+    /// Read-only cache to take images from before downloading them
+    #[clap(long, global = true, env = "TIFFER_SHARED_CACHE_DIR")]
+    shared_cache_dir: Option<String>,
+}
+
+impl CacheArgs {
+    /// Command line and environment take precedence over the config file.
+    fn apply(self, config: &mut Config) {
+        if let Some(dir) = self.cache_dir {
+            config.cache.dir = Some(dir);
+        }
+        if let Some(dir) = self.shared_cache_dir {
+            config.cache.shared_dir = Some(dir);
+        }
+    }
+}
+
+/// The decks to work on, and which of their cards and printings to use.
+#[derive(Args, Debug)]
+struct DeckArgs {
+    #[clap(required = true)]
+    sources: Vec<Source>,
+
+    #[command(flatten)]
+    resolve: ResolveArgs,
+
+    #[command(flatten)]
+    selection: SelectionArgs,
+}
+
+#[derive(Args, Debug)]
+struct ResolveArgs {
+    /// Leave out cards that can't be resolved or downloaded and list them at the end
+    #[clap(long, overrides_with = "no_tolerant")]
+    tolerant: bool,
+    /// Stop at the first card that can't be resolved or downloaded
+    #[clap(long, overrides_with = "tolerant")]
+    no_tolerant: bool,
+    /// Use the closest match for misspelled card names without asking
+    #[clap(long)]
+    accept_fuzzy: bool,
+}
+
+impl ResolveArgs {
+    /// Command line flags take precedence over the config file.
+    fn apply(&self, config: &mut Config) {
+        if self.tolerant {
+            config.print.tolerant = true;
+        } else if self.no_tolerant {
+            config.print.tolerant = false;
+        }
+        if self.accept_fuzzy {
+            config.print.fuzzy = FuzzyMatch::Accept;
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct SelectionArgs {
+    /// Collection (CSV or decklist) of cards that don't need to be printed
+    #[clap(short, long)]
+    owned: Option<PathBuf>,
+    /// How deck cards are matched against the owned collection
+    #[clap(long, value_enum, default_value_t = OwnedMatch::Name)]
+    owned_match: OwnedMatch,
+    /// Print cards in this language (e.g. "ja", "de") where such a printing exists
     #[clap(short, long)]
+    lang: Option<String>,
+
+    #[command(flatten)]
+    printing: PrintingPolicy,
+}
+
+#[derive(Args, Debug)]
+struct DownloadArgs {
+    /// Leave out the tokens the deck's cards create
+    #[clap(short, long, overrides_with = "tokens")]
     no_tokens: bool,
+    /// Print the tokens the deck's cards create
+    #[clap(long, overrides_with = "no_tokens")]
+    tokens: bool,
+    /// Size of the card images to download
+    #[clap(short, long, value_enum)]
+    image_version: Option<ImageVersion>,
+    /// Check cached images for better scans on Scryfall
+    #[clap(long, overrides_with = "no_refresh")]
+    refresh: bool,
+    /// Use cached images as they are
+    #[clap(long, overrides_with = "refresh")]
+    no_refresh: bool,
+}
+
+impl DownloadArgs {
+    /// Overrides the configured generation options with the ones given on the command line.
+    fn apply(&self, options: &mut DeckGenerationOptions) {
+        if self.no_tokens {
+            options.print_tokens = false;
+        } else if self.tokens {
+            options.print_tokens = true;
+        }
+        if let Some(image_version) = self.image_version {
+            options.image_version = image_version;
+        }
+        if self.refresh {
+            options.refresh = true;
+        } else if self.no_refresh {
+            options.refresh = false;
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct RenderArgs {
     #[clap(short, long)]
     filename: Option<String>,
+    /// Paper size to lay the cards out on
+    #[clap(short, long, value_enum)]
+    paper: Option<PaperSize>,
+    /// How to set apart decks when printing several sources in one job
+    #[clap(short, long, value_enum)]
+    separator: Option<Separator>,
+    /// Print an outline with the card's name for every card left out by --tolerant
+    #[clap(long, overrides_with = "no_placeholders")]
+    placeholders: bool,
+    /// Leave no room for cards left out by --tolerant
+    #[clap(long, overrides_with = "placeholders")]
+    no_placeholders: bool,
+}
 
-    source: Source,
+#[derive(Args, Debug)]
+struct DryRunArgs {
+    /// Print the resolved cards instead of downloading images and generating a PDF
+    #[clap(long)]
+    dry_run: bool,
+    /// How to print the resolved cards of a dry run
+    #[clap(long, value_enum, default_value_t = ListFormat::Table, requires = "dry_run")]
+    format: ListFormat,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+impl RenderArgs {
+    /// Overrides the configured generation options with the ones given on the command line.
+    fn apply(&self, options: &mut DeckGenerationOptions) {
+        if let Some(filename) = &self.filename {
+            options.filename = Some(filename.clone());
+        }
+        if let Some(paper) = self.paper {
+            options.paper = paper;
+        }
+        if let Some(separator) = self.separator {
+            options.separator = separator;
+        }
+        if self.placeholders {
+            options.placeholders = true;
+        } else if self.no_placeholders {
+            options.placeholders = false;
+        }
+    }
+}
+
+/// The options from the config, reporting to `reporter`.
+fn options(config: &Config, reporter: &Reporter) -> DeckGenerationOptions {
+    DeckGenerationOptions {
+        reporter: reporter.clone(),
+        ..config.options()
+    }
+}
 
-    let deck: Deck = match args.source {
+async fn get_deck(source: Source, config: &Config, reporter: &Reporter) -> anyhow::Result<Deck> {
+    reporter.event(&Event::ResolveStarted {
+        source: source.to_string(),
+    });
+
+    let deck = match source {
         Source::Link(url) => {
-            println!("Fetching deck from remote: {}", url);
-            get_remote_deck(url).await?
+            reporter.status(&format!("Fetching deck from remote: {}", url));
+            get_remote_deck(url, &config.sources, reporter).await?
         }
         Source::File(path) => {
-            println!("Deck from local: {}", path.to_str().unwrap());
-            get_local_deck(path).await?
+            reporter.status(&format!("Deck from local: {}", path.to_str().unwrap()));
+            get_local_deck(path, &options(config, reporter)).await?
+        }
+        Source::Snapshot(path) => {
+            reporter.status(&format!("Deck from snapshot: {}", path.display()));
+            Deck::load(&path)?
         }
     };
 
-    deck.generate(tiffer::deck::DeckGenerationOptions {
-        filename: args.filename,
-        print_tokens: !args.no_tokens,
-    })
-    .await?;
+    reporter.event(&Event::DeckResolved {
+        name: deck.name.clone(),
+        cards: deck.card_count(),
+        tokens: deck.token_count(),
+    });
+
+    Ok(deck)
+}
+
+/// Fetches and merges the given decks, then narrows them down to the cards
+/// and printings to use.
+async fn resolve(args: DeckArgs, config: &Config, reporter: &Reporter) -> anyhow::Result<Deck> {
+    let mut decks = Vec::new();
+    for source in args.sources {
+        decks.push(get_deck(source, config, reporter).await?);
+    }
+
+    select(
+        Deck::merge(decks),
+        args.selection,
+        config.print.tolerant,
+        reporter,
+    )
+    .await
+}
+
+async fn select(
+    mut deck: Deck,
+    selection: SelectionArgs,
+    tolerant: bool,
+    reporter: &Reporter,
+) -> anyhow::Result<Deck> {
+    if let Some(path) = selection.owned {
+        reporter.status("Reading owned collection...");
+        let collection = get_collection(path, selection.owned_match, &deck, reporter).await?;
+        let skipped = deck.remove_owned(&collection);
+
+        if !skipped.is_empty() {
+            reporter.status("Skipping owned cards:");
+            for (name, count) in &skipped {
+                reporter.status(&format!("  {} {}", count, name));
+            }
+        }
+    }
+
+    if deck.total_cards() == 0 {
+        return Ok(deck);
+    }
+
+    deck.apply_printing_policy(&selection.printing, tolerant, reporter)
+        .await?;
+
+    if let Some(lang) = &selection.lang {
+        deck.localize(lang, tolerant, reporter).await?;
+    }
+
+    Ok(deck)
+}
+
+/// Prints every resolved printing of the deck with how many copies are used.
+fn list(
+    deck: &Deck,
+    format: ListFormat,
+    output: OutputFormat,
+    reporter: &Reporter,
+) -> anyhow::Result<()> {
+    let listing = deck.listing();
+
+    if output == OutputFormat::Json {
+        reporter.event(&Event::Listing(listing));
+        return Ok(());
+    }
+
+    match format {
+        ListFormat::Json => println!("{}", serde_json::to_string_pretty(&listing)?),
+        ListFormat::Table => print_table(&listing),
+    }
 
     Ok(())
 }
+
+fn print_table(listing: &DeckListing) {
+    let header = [
+        "SECTION",
+        "QTY",
+        "NAME",
+        "SET",
+        "NUMBER",
+        "LANG",
+        "SCRYFALL ID",
+        "FLAGS",
+    ]
+    .map(str::to_string);
+
+    let rows: Vec<[String; 8]> = listing
+        .entries
+        .iter()
+        .map(|entry| {
+            let finish = entry
+                .finish
+                .filter(|finish| *finish != Finish::Nonfoil)
+                .map(|finish| finish.to_string());
+            let flags: Vec<&str> = [(entry.token, "token"), (entry.backface, "backface")]
+                .into_iter()
+                .filter_map(|(set, flag)| set.then_some(flag))
+                .chain(finish.as_deref())
+                .collect();
+
+            [
+                entry.section.clone(),
+                entry.quantity.to_string(),
+                entry.name.clone(),
+                entry.set.clone().unwrap_or_else(|| "-".to_string()),
+                entry
+                    .collector_number
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                entry.lang.clone().unwrap_or_else(|| "en".to_string()),
+                entry.scryfall_id.clone(),
+                flags.join(","),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|column| column.chars().count());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    println!("{}", listing.name);
+    for row in std::iter::once(&header).chain(&rows) {
+        let columns: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect();
+        println!("{}", columns.join("  ").trim_end());
+    }
+}
+
+/// Writes the deck in another tool's format.
+fn export(
+    deck: &Deck,
+    format: ExportFormat,
+    filename: Option<String>,
+    reporter: &Reporter,
+) -> anyhow::Result<()> {
+    let exported = deck.export(format)?;
+    let path = filename.unwrap_or_else(|| format!("{}.{}", deck.name, format.extension()));
+
+    if path == "-" {
+        print!("{}", exported);
+        return Ok(());
+    }
+
+    std::fs::write(&path, exported)?;
+    reporter.status(&format!("Exported deck to {}", path));
+    reporter.event(&Event::Finished {
+        path: Some(path),
+        cards: deck.card_count(),
+        tokens: 0,
+        pages: 0,
+        failed: failed(&deck.failures),
+    });
+
+    Ok(())
+}
+
+/// Resolves each deck on its own and reports every card that fails.
+async fn validate(
+    sources: Vec<Source>,
+    mut config: Config,
+    reporter: &Reporter,
+) -> anyhow::Result<()> {
+    // Keep going after the first bad card, so that all of them are reported
+    config.print.tolerant = true;
+    config.print.fuzzy = FuzzyMatch::Suggest;
+
+    let total = sources.len();
+    let mut failed = 0;
+
+    for source in sources {
+        let description = source.to_string();
+
+        match get_deck(source, &config, reporter).await {
+            Ok(deck) if !deck.failures.is_empty() => {
+                reporter.status(&format!("FAILED {}:", description));
+                report(&deck.failures, reporter);
+                failed += 1;
+            }
+            Ok(deck) => reporter.status(&format!(
+                "OK {}: {} cards, {} tokens",
+                description,
+                fronts(&deck.cards),
+                fronts(&deck.tokens)
+            )),
+            Err(e) => {
+                reporter.status(&format!("FAILED {}: {}", description, e));
+                reporter.event(&Event::Error {
+                    message: format!("{}: {:#}", description, e),
+                });
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} decks are invalid", failed, total));
+    }
+
+    Ok(())
+}
+
+fn show_config(action: ConfigCommand, config: &Config, reporter: &Reporter) -> anyhow::Result<()> {
+    match action {
+        ConfigCommand::Show => {
+            let files: Vec<String> = Config::files()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            let config = config.redacted();
+
+            for file in &files {
+                reporter.status(&format!("# from {}", file));
+            }
+            reporter.status(toml::to_string_pretty(&config)?.trim_end());
+            reporter.event(&Event::ConfigShown { files, config });
+        }
+    }
+
+    Ok(())
+}
+
+fn manage_cache(
+    action: CacheCommand,
+    cache: CacheConfig,
+    reporter: &Reporter,
+) -> anyhow::Result<()> {
+    let dir = cache.dir()?;
+
+    let removed = match action {
+        CacheCommand::Path => {
+            reporter.status(&dir);
+            if let Some(shared_dir) = &cache.shared_dir {
+                reporter.status(&format!("{} (shared)", shared_dir));
+            }
+            reporter.event(&Event::CachePaths {
+                dir,
+                shared_dir: cache.shared_dir,
+            });
+            return Ok(());
+        }
+        CacheCommand::Stats => {
+            let stats = cache::stats(&dir)?;
+            let oldest = stats
+                .oldest
+                .map(|oldest| humantime::format_rfc3339_seconds(oldest).to_string());
+
+            reporter.status(&format!("Images: {}", stats.count));
+            reporter.status(&format!("Size: {}", ByteSize(stats.size)));
+            if let Some(oldest) = &oldest {
+                reporter.status(&format!("Oldest: {}", oldest));
+            }
+            reporter.event(&Event::CacheStats {
+                images: stats.count,
+                size: stats.size,
+                oldest,
+            });
+            return Ok(());
+        }
+        CacheCommand::Clean { older_than } => cache::clean(&dir, older_than)?,
+        CacheCommand::Verify => cache::verify(&dir, reporter)?,
+        CacheCommand::Prune { max_size } => cache::prune(&dir, max_size.as_u64())?,
+    };
+
+    let size = removed.iter().map(|entry| entry.size).sum();
+    reporter.status(&format!(
+        "Removed {} images ({})",
+        removed.len(),
+        ByteSize(size)
+    ));
+    reporter.event(&Event::CacheCleaned {
+        removed: removed.len(),
+        size,
+    });
+
+    Ok(())
+}
+
+/// Copies of cards, not counting back faces.
+fn fronts(cards: &[Card]) -> usize {
+    cards
+        .iter()
+        .filter(|card| !card.backface)
+        .map(|card| card.quantity as usize)
+        .sum()
+}
+
+/// Lists the cards that were left out in tolerant mode.
+fn report(failures: &[Failure], reporter: &Reporter) {
+    if failures.is_empty() {
+        return;
+    }
+
+    reporter.status("Could not print:");
+    for failure in failures {
+        reporter.status(&format!(
+            "  {} {} ({}): {}",
+            failure.quantity, failure.card, failure.stage, failure.error
+        ));
+        reporter.event(&Event::CardFailed(failure.clone()));
+    }
+}
+
+fn failed(failures: &[Failure]) -> usize {
+    failures
+        .iter()
+        .map(|failure| failure.quantity as usize)
+        .sum()
+}
+
+/// Reports that there was nothing left to print.
+fn nothing_to_print(message: &str, failures: &[Failure], reporter: &Reporter) {
+    reporter.status(message);
+    report(failures, reporter);
+    reporter.event(&Event::Finished {
+        path: None,
+        cards: 0,
+        tokens: 0,
+        pages: 0,
+        failed: failed(failures),
+    });
+}
+
+async fn run(
+    command: Command,
+    mut config: Config,
+    output: OutputFormat,
+    reporter: &Reporter,
+) -> anyhow::Result<()> {
+    match command {
+        Command::Print {
+            deck,
+            download,
+            render,
+            dry_run,
+        } => {
+            deck.resolve.apply(&mut config);
+            let deck = resolve(deck, &config, reporter).await?;
+            if dry_run.dry_run {
+                report(&deck.failures, reporter);
+                return list(&deck, dry_run.format, output, reporter);
+            }
+
+            if deck.total_cards() == 0 {
+                nothing_to_print("Nothing to print", &deck.failures, reporter);
+                return Ok(());
+            }
+
+            let mut options = options(&config, reporter);
+            download.apply(&mut options);
+            render.apply(&mut options);
+
+            report(&deck.generate(options).await?, reporter);
+        }
+        Command::Fetch { deck, download } => {
+            deck.resolve.apply(&mut config);
+            let deck = resolve(deck, &config, reporter).await?;
+
+            let mut options = options(&config, reporter);
+            download.apply(&mut options);
+
+            let mut failures = deck.failures.clone();
+            failures.extend(deck.download(options.clone()).await?);
+            report(&failures, reporter);
+
+            reporter.event(&Event::Finished {
+                path: None,
+                cards: deck.card_count(),
+                tokens: match options.print_tokens {
+                    true => deck.token_count(),
+                    false => 0,
+                },
+                pages: 0,
+                failed: failed(&failures),
+            });
+        }
+        Command::List { deck, format, save } => {
+            deck.resolve.apply(&mut config);
+            let deck = resolve(deck, &config, reporter).await?;
+            report(&deck.failures, reporter);
+            list(&deck, format, output, reporter)?;
+
+            if let Some(path) = save {
+                deck.save(&path)?;
+                if !path.to_string_lossy().ends_with(SNAPSHOT_EXTENSION) {
+                    reporter.warning(&format!(
+                        "{} doesn't end with {}, it won't be recognized as a snapshot",
+                        path.display(),
+                        SNAPSHOT_EXTENSION
+                    ));
+                }
+            }
+        }
+        Command::Export {
+            deck,
+            format,
+            filename,
+        } => {
+            deck.resolve.apply(&mut config);
+            let deck = resolve(deck, &config, reporter).await?;
+            report(&deck.failures, reporter);
+            export(&deck, format, filename, reporter)?
+        }
+        Command::Validate { sources } => validate(sources, config, reporter).await?,
+        Command::Diff {
+            by,
+            selection,
+            download,
+            render,
+            dry_run,
+            resolve,
+            old,
+            new,
+        } => {
+            resolve.apply(&mut config);
+            let old = get_deck(old, &config, reporter).await?;
+            let new = get_deck(new, &config, reporter).await?;
+            let diff = old.diff(&new, by);
+
+            for (name, count) in &diff.removed {
+                reporter.status(&format!("- {} {}", count, name));
+            }
+
+            let added = select(diff.added, selection, config.print.tolerant, reporter).await?;
+            if dry_run.dry_run {
+                report(&added.failures, reporter);
+                return list(&added, dry_run.format, output, reporter);
+            }
+            if added.total_cards() == 0 {
+                nothing_to_print("No cards added", &added.failures, reporter);
+                return Ok(());
+            }
+
+            let mut options = options(&config, reporter);
+            options.separator = Separator::None;
+            download.apply(&mut options);
+            render.apply(&mut options);
+
+            report(&added.generate(options).await?, reporter);
+        }
+        Command::Cache { action } => manage_cache(action, config.cache, reporter)?,
+        Command::Config { action } => show_config(action, &config, reporter)?,
+    }
+
+    Ok(())
+}
+
+/// Passes the library's warnings on to the reporter, e.g. retried requests.
+struct ReportingLogger(Reporter);
+
+impl log::Log for ReportingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.warning(&record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Cli::parse();
+    let reporter = match args.output {
+        OutputFormat::Text if args.command.writes_stdout() => {
+            Reporter::new(TerminalReporter::stderr())
+        }
+        OutputFormat::Text => Reporter::new(TerminalReporter::default()),
+        OutputFormat::Json
+            if matches!(args.command, Command::Export { .. }) && args.command.writes_stdout() =>
+        {
+            anyhow::bail!("Can't export to stdout with --output json, give a file name")
+        }
+        OutputFormat::Json => Reporter::new(JsonReporter),
+    };
+
+    let logger = Box::leak(Box::new(ReportingLogger(reporter.clone())));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+
+    let result = match Config::load() {
+        Ok(mut config) => {
+            args.cache.apply(&mut config);
+            run(args.command, config, args.output, &reporter).await
+        }
+        Err(e) => Err(e.into()),
+    };
+
+    match result {
+        // Programs reading the events shouldn't have to parse stderr as well
+        Err(e) if args.output == OutputFormat::Json => {
+            reporter.event(&Event::Error {
+                message: format!("{:#}", e),
+            });
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use crate::deck::Deck;
+use crate::error::Result;
+use crate::progress::{Progress, Reporter};
+use crate::scryfall::ScryfallClient;
+
+/// Which printing to pick among the ones allowed by a `PrintingPolicy`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PrintingPreference {
+    /// Keep the printing given by the source if it is allowed, else use the newest one.
+    #[default]
+    Current,
+    /// The most recently released printing.
+    Newest,
+    /// The first printing of the card.
+    Oldest,
+}
+
+/// Rules for choosing printings of cards whose printing wasn't picked explicitly.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+#[cfg_attr(
+    feature = "cli",
+    command(about = None, next_help_heading = "Printing selection")
+)]
+pub struct PrintingPolicy {
+    /// Which allowed printing to prefer
+    #[cfg_attr(
+        feature = "cli",
+        arg(long = "printing", value_enum, default_value_t = PrintingPreference::Current)
+    )]
+    pub prefer: PrintingPreference,
+    /// Only allow printings with this frame
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_parser = ["1993", "1997", "2003", "2015", "future"])
+    )]
+    pub frame: Option<String>,
+    /// Skip borderless printings
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub no_borderless: bool,
+    /// Skip showcase and extended art printings
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub no_showcase: bool,
+    /// Skip promo printings
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub no_promo: bool,
+    /// Skip full-art printings
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub no_full_art: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ScryfallPrint {
+    id: String,
+    set: String,
+    collector_number: String,
+    frame: String,
+    border_color: String,
+    #[serde(default)]
+    frame_effects: Vec<String>,
+    promo: bool,
+    full_art: bool,
+    #[serde(default)]
+    image_status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScryfallList {
+    data: Vec<ScryfallPrint>,
+    #[serde(default)]
+    next_page: Option<String>,
+}
+
+impl PrintingPolicy {
+    /// Whether the policy would change anything at all.
+    pub fn is_default(&self) -> bool {
+        self.prefer == PrintingPreference::Current
+            && self.frame.is_none()
+            && !self.no_borderless
+            && !self.no_showcase
+            && !self.no_promo
+            && !self.no_full_art
+    }
+
+    fn allows(&self, print: &ScryfallPrint) -> bool {
+        let showcase = print
+            .frame_effects
+            .iter()
+            .any(|effect| effect == "showcase" || effect == "extendedart");
+
+        self.frame
+            .as_ref()
+            .is_none_or(|frame| *frame == print.frame)
+            && !(self.no_borderless && print.border_color == "borderless")
+            && !(self.no_showcase && showcase)
+            && !(self.no_promo && print.promo)
+            && !(self.no_full_art && print.full_art)
+    }
+
+    /// Picks a printing from a list ordered newest first.
+    fn choose<'a>(&self, current: &str, prints: &'a [ScryfallPrint]) -> Option<&'a ScryfallPrint> {
+        let mut allowed = prints.iter().filter(|print| self.allows(print));
+
+        match self.prefer {
+            PrintingPreference::Current => {
+                let allowed: Vec<_> = allowed.collect();
+                allowed
+                    .iter()
+                    .find(|print| print.id == current)
+                    .or(allowed.first())
+                    .copied()
+            }
+            PrintingPreference::Newest => allowed.next(),
+            PrintingPreference::Oldest => allowed.next_back(),
+        }
+    }
+}
+
+impl Deck {
+    /// Re-resolves the printing of every card the source didn't pin to a
+    /// specific printing. Cards without any allowed printing are left as is,
+    /// and so are cards that can't be looked up when `tolerant`.
+    pub async fn apply_printing_policy(
+        &mut self,
+        policy: &PrintingPolicy,
+        tolerant: bool,
+        reporter: &Reporter,
+    ) -> Result<()> {
+        if policy.is_default() {
+            return Ok(());
+        }
+
+        let mut cards: Vec<(String, String)> = self
+            .cards
+            .iter()
+            .filter(|card| !card.pinned)
+            .map(|card| (card.scryfall_id.clone(), card.name.clone()))
+            .collect();
+        cards.sort();
+        cards.dedup();
+
+        reporter.status("Choosing printings...");
+        let progress = Progress::new(reporter, "printings", cards.len() as u64);
+
+        let tasks: Vec<_> = cards
+            .into_iter()
+            .map(|(id, name)| {
+                let policy = policy.clone();
+                let progress = progress.clone();
+                task::spawn(async move {
+                    let prints = get_printings(&name).await;
+                    progress.inc(&name);
+                    prints.map_err(|e| (name.clone(), e)).map(|prints| {
+                        let chosen = policy.choose(&id, &prints).cloned();
+                        if chosen.is_none() {
+                            log::info!("No printing of {} matches the policy, keeping it", name);
+                        }
+                        (id, chosen)
+                    })
+                })
+            })
+            .collect();
+
+        let mut chosen = HashMap::new();
+        for task in tasks {
+            match task.await? {
+                Ok((id, Some(print))) => {
+                    chosen.insert(id, print);
+                }
+                Ok((_, None)) => {}
+                Err((name, e)) if tolerant => {
+                    log::warn!("{}, keeping the printing of {}", e, name);
+                }
+                Err((_, e)) => return Err(e),
+            }
+        }
+        progress.finish();
+
+        for card in self.cards.iter_mut().filter(|card| !card.pinned) {
+            if let Some(print) = chosen.get(&card.scryfall_id) {
+                card.scryfall_id = print.id.clone();
+                card.set = Some(print.set.clone());
+                card.collector_number = Some(print.collector_number.clone());
+                card.image_status = print.image_status.clone();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Every paper printing of a card, newest first. Cards like basic lands
+/// have more printings than fit on one page of results.
+async fn get_printings(name: &str) -> Result<Vec<ScryfallPrint>> {
+    let client = ScryfallClient::shared()?;
+    let mut list = client
+        .get_json::<ScryfallList>(
+            "https://api.scryfall.com/cards/search",
+            &[
+                ("q", &format!("!\"{}\" game:paper", name)),
+                ("unique", "prints"),
+                ("order", "released"),
+                ("dir", "desc"),
+            ],
+        )
+        .await?;
+
+    let mut prints = Vec::new();
+    while let Some(page) = list {
+        prints.extend(page.data);
+        list = match page.next_page {
+            // The next page URL already holds the whole query
+            Some(url) => client.get_json::<ScryfallList>(&url, &[]).await?,
+            None => None,
+        };
+    }
+
+    Ok(prints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(id: &str) -> ScryfallPrint {
+        ScryfallPrint {
+            id: id.to_string(),
+            set: "nec".to_string(),
+            collector_number: "134".to_string(),
+            frame: "2015".to_string(),
+            border_color: "black".to_string(),
+            frame_effects: Vec::new(),
+            promo: false,
+            full_art: false,
+            image_status: None,
+        }
+    }
+
+    fn chosen(policy: &PrintingPolicy, current: &str, prints: &[ScryfallPrint]) -> Option<String> {
+        policy.choose(current, prints).map(|print| print.id.clone())
+    }
+
+    #[test]
+    fn default_policy() {
+        assert!(PrintingPolicy::default().is_default());
+        assert!(!PrintingPolicy {
+            no_promo: true,
+            ..PrintingPolicy::default()
+        }
+        .is_default());
+    }
+
+    #[test]
+    fn current_kept_when_allowed() {
+        let prints = [print("new"), print("mid"), print("old")];
+
+        assert_eq!(
+            chosen(&PrintingPolicy::default(), "mid", &prints).as_deref(),
+            Some("mid")
+        );
+    }
+
+    #[test]
+    fn current_falls_back_to_newest() {
+        let prints = [
+            print("new"),
+            ScryfallPrint {
+                promo: true,
+                ..print("mid")
+            },
+            print("old"),
+        ];
+        let policy = PrintingPolicy {
+            no_promo: true,
+            ..PrintingPolicy::default()
+        };
+
+        assert_eq!(chosen(&policy, "mid", &prints).as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn newest_and_oldest() {
+        let prints = [print("new"), print("mid"), print("old")];
+        let policy = |prefer| PrintingPolicy {
+            prefer,
+            ..PrintingPolicy::default()
+        };
+
+        assert_eq!(
+            chosen(&policy(PrintingPreference::Newest), "mid", &prints).as_deref(),
+            Some("new")
+        );
+        assert_eq!(
+            chosen(&policy(PrintingPreference::Oldest), "mid", &prints).as_deref(),
+            Some("old")
+        );
+    }
+
+    #[test]
+    fn nothing_allowed() {
+        let policy = PrintingPolicy {
+            frame: Some("1993".to_string()),
+            ..PrintingPolicy::default()
+        };
+
+        assert_eq!(chosen(&policy, "a", &[print("a")]), None);
+    }
+
+    #[test]
+    fn filters() {
+        let policies = [
+            PrintingPolicy {
+                frame: Some("1997".to_string()),
+                ..PrintingPolicy::default()
+            },
+            PrintingPolicy {
+                no_borderless: true,
+                ..PrintingPolicy::default()
+            },
+            PrintingPolicy {
+                no_showcase: true,
+                ..PrintingPolicy::default()
+            },
+            PrintingPolicy {
+                no_promo: true,
+                ..PrintingPolicy::default()
+            },
+            PrintingPolicy {
+                no_full_art: true,
+                ..PrintingPolicy::default()
+            },
+        ];
+        let rejected = [
+            print("2015 frame"),
+            ScryfallPrint {
+                border_color: "borderless".to_string(),
+                ..print("borderless")
+            },
+            ScryfallPrint {
+                frame_effects: vec!["extendedart".to_string()],
+                ..print("extended art")
+            },
+            ScryfallPrint {
+                promo: true,
+                ..print("promo")
+            },
+            ScryfallPrint {
+                full_art: true,
+                ..print("full art")
+            },
+        ];
+
+        for (policy, rejected) in policies.iter().zip(&rejected) {
+            assert!(!policy.allows(rejected), "{} allowed", rejected.id);
+            assert!(
+                PrintingPolicy::default().allows(rejected),
+                "{} rejected",
+                rejected.id
+            );
+        }
+        assert!(policies[0].allows(&ScryfallPrint {
+            frame: "1997".to_string(),
+            ..print("1997 frame")
+        }));
+        assert!(!policies[2].allows(&ScryfallPrint {
+            frame_effects: vec!["showcase".to_string()],
+            ..print("showcase")
+        }));
+    }
+}
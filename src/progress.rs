@@ -0,0 +1,231 @@
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "progress")]
+use std::{collections::HashMap, io::IsTerminal, sync::Mutex};
+
+#[cfg(feature = "progress")]
+use indicatif::ProgressBar;
+
+use crate::events::Event;
+
+/// Receives what tiffer is doing, so that programs embedding it can show
+/// progress in their own UI. Every method does nothing by default.
+///
+/// Stages are named "resolve", "localize", "printings", "collection",
+/// "download", "render" and "verify". Methods may be called from several threads at once.
+pub trait ProgressReporter: Send + Sync {
+    /// A stage starts with `total` items to go through.
+    fn start(&self, _stage: &'static str, _total: u64) {}
+
+    /// One more item of a stage is done.
+    fn advance(&self, _stage: &'static str, _item: &str, _done: u64, _total: u64) {}
+
+    /// A stage is done.
+    fn finish(&self, _stage: &'static str) {}
+
+    /// Tells a person what is going on.
+    fn status(&self, _message: &str) {}
+
+    /// Reports a problem that doesn't stop tiffer.
+    fn warning(&self, _message: &str) {}
+
+    /// Something tiffer did, for programs following along.
+    fn event(&self, _event: &Event) {}
+
+    /// Whether to use `corrected` for the misspelled card `name`, asked when
+    /// `DeckGenerationOptions::fuzzy` is `FuzzyMatch::Ask`. Declines by default.
+    fn confirm_correction(&self, _name: &str, _corrected: &str) -> bool {
+        false
+    }
+}
+
+/// Reports nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Silent;
+
+impl ProgressReporter for Silent {}
+
+/// A shared `ProgressReporter`, silent unless set.
+#[derive(Clone)]
+pub struct Reporter(Arc<dyn ProgressReporter>);
+
+impl Reporter {
+    pub fn new(reporter: impl ProgressReporter + 'static) -> Self {
+        Reporter(Arc::new(reporter))
+    }
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Reporter::new(Silent)
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reporter")
+    }
+}
+
+impl Deref for Reporter {
+    type Target = dyn ProgressReporter;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+/// Progress bars and messages on the terminal, asking there about
+/// misspelled card names.
+#[cfg(feature = "progress")]
+#[derive(Debug, Default)]
+pub struct TerminalReporter {
+    bars: Mutex<HashMap<&'static str, ProgressBar>>,
+    /// Print status messages to stderr rather than stdout.
+    stderr: bool,
+}
+
+#[cfg(feature = "progress")]
+impl TerminalReporter {
+    /// Keeps stdout free for output such as an exported deck.
+    pub fn stderr() -> Self {
+        TerminalReporter {
+            stderr: true,
+            ..TerminalReporter::default()
+        }
+    }
+
+    fn bar(&self, stage: &'static str) -> Option<ProgressBar> {
+        self.bars.lock().ok()?.get(stage).cloned()
+    }
+
+    fn active_bar(&self) -> Option<ProgressBar> {
+        self.bars.lock().ok()?.values().next().cloned()
+    }
+}
+
+#[cfg(feature = "progress")]
+impl ProgressReporter for TerminalReporter {
+    fn start(&self, stage: &'static str, total: u64) {
+        if let Ok(mut bars) = self.bars.lock() {
+            bars.insert(stage, ProgressBar::new(total));
+        }
+    }
+
+    fn advance(&self, stage: &'static str, _item: &str, _done: u64, _total: u64) {
+        if let Some(bar) = self.bar(stage) {
+            bar.inc(1);
+        }
+    }
+
+    fn finish(&self, stage: &'static str) {
+        let bar = self
+            .bars
+            .lock()
+            .ok()
+            .and_then(|mut bars| bars.remove(stage));
+        if let Some(bar) = bar {
+            bar.finish_and_clear();
+        }
+    }
+
+    fn status(&self, message: &str) {
+        let print = || match self.stderr {
+            true => eprintln!("{}", message),
+            false => println!("{}", message),
+        };
+
+        match self.active_bar() {
+            Some(bar) => bar.suspend(print),
+            None => print(),
+        }
+    }
+
+    fn warning(&self, message: &str) {
+        match self.active_bar() {
+            Some(bar) => bar.suspend(|| eprintln!("{}", message)),
+            None => eprintln!("{}", message),
+        }
+    }
+
+    fn confirm_correction(&self, name: &str, corrected: &str) -> bool {
+        if !std::io::stdin().is_terminal() {
+            return false;
+        }
+
+        let ask = || {
+            eprint!(
+                "\"{}\" not found, use \"{}\" instead? [Y/n] ",
+                name, corrected
+            );
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer).is_ok()
+                && matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes")
+        };
+
+        match self.active_bar() {
+            Some(bar) => bar.suspend(ask),
+            None => ask(),
+        }
+    }
+}
+
+/// Newline-delimited JSON events on stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonReporter;
+
+impl ProgressReporter for JsonReporter {
+    fn advance(&self, stage: &'static str, item: &str, done: u64, total: u64) {
+        self.event(&Event::Progress {
+            stage,
+            item: item.to_string(),
+            done,
+            total,
+        });
+    }
+
+    fn warning(&self, message: &str) {
+        self.event(&Event::Warning {
+            message: message.to_string(),
+        });
+    }
+
+    fn event(&self, event: &Event) {
+        match serde_json::to_string(event) {
+            Ok(line) => println!("{}", line),
+            Err(e) => log::error!("Failed to serialize {:?}: {}", event, e),
+        }
+    }
+}
+
+/// Counts the items of one stage for a reporter.
+#[derive(Debug, Clone)]
+pub(crate) struct Progress {
+    reporter: Reporter,
+    stage: &'static str,
+    total: u64,
+    done: Arc<AtomicU64>,
+}
+
+impl Progress {
+    pub(crate) fn new(reporter: &Reporter, stage: &'static str, total: u64) -> Self {
+        reporter.start(stage, total);
+        Progress {
+            reporter: reporter.clone(),
+            stage,
+            total,
+            done: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub(crate) fn inc(&self, item: &str) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.reporter.advance(self.stage, item, done, self.total);
+    }
+
+    pub(crate) fn finish(&self) {
+        self.reporter.finish(self.stage);
+    }
+}
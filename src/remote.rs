@@ -1,98 +1,212 @@
 use std::collections::HashMap;
 
-use derive_more::Display;
-use serde::Deserialize;
-use thiserror::Error;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
 
 use url::Url;
 
-use crate::deck::Card;
 use crate::deck::Deck;
+use crate::deck::{Card, Finish};
+use crate::error::{Error, Result};
+use crate::events::Event;
+use crate::progress::Reporter;
 
 const MOXFIELD_API_URL: &str = "https://api2.moxfield.com/v2/decks/all";
 
-#[derive(Debug, Display, Error)]
-enum DeckDownloadError {
-    UnsupportedWebsite,
-}
-
 enum Website {
     Moxfield,
 }
 
-pub async fn get_remote_deck(url: Url) -> anyhow::Result<Deck> {
+/// Credentials for the deck building websites decks are fetched from.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SourcesConfig {
+    pub moxfield: Credentials,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Credentials {
+    /// Sent as a bearer token, needed for private decks.
+    pub token: Option<String>,
+    /// Some APIs only answer clients they know.
+    pub user_agent: Option<String>,
+}
+
+impl SourcesConfig {
+    /// The same config with every token hidden, safe to print.
+    pub fn redacted(&self) -> Self {
+        SourcesConfig {
+            moxfield: self.moxfield.redacted(),
+        }
+    }
+}
+
+impl Credentials {
+    fn redacted(&self) -> Self {
+        Credentials {
+            token: self.token.as_ref().map(|_| "***".to_string()),
+            ..self.clone()
+        }
+    }
+
+    fn client(&self) -> Result<reqwest::Client> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &self.token {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))
+                    .map_err(|e| Error::Config(format!("invalid token: {}", e)))?,
+            );
+        }
+
+        let mut builder = reqwest::Client::builder().default_headers(headers);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        builder
+            .build()
+            .map_err(|e| Error::Config(format!("invalid credentials: {}", e)))
+    }
+}
+
+pub async fn get_remote_deck(
+    url: Url,
+    sources: &SourcesConfig,
+    reporter: &Reporter,
+) -> Result<Deck> {
     let website = parse_url(&url)?;
     match website {
-        Website::Moxfield => Ok(get_moxfield_deck(url).await?),
+        Website::Moxfield => Ok(get_moxfield_deck(url, &sources.moxfield, reporter).await?),
     }
 }
 
-fn parse_url(url: &Url) -> anyhow::Result<Website> {
+fn parse_url(url: &Url) -> Result<Website> {
     match url.host_str() {
         Some("moxfield.com") => Ok(Website::Moxfield),
-        _ => Err(DeckDownloadError::UnsupportedWebsite.into()),
+        _ => Err(Error::UnsupportedWebsite(url.to_string())),
     }
 }
 
-async fn get_moxfield_deck(url: Url) -> anyhow::Result<Deck> {
+async fn get_moxfield_deck(
+    url: Url,
+    credentials: &Credentials,
+    reporter: &Reporter,
+) -> Result<Deck> {
     #[derive(Debug, Deserialize)]
     struct MoxfieldCard {
         pub quantity: u32,
         pub card: MoxfieldCardInfo,
+        #[serde(default)]
+        pub finish: Option<Finish>,
     }
 
     #[derive(Debug, Deserialize)]
     struct MoxfieldCardInfo {
         pub scryfall_id: String,
         pub name: String,
+        #[serde(default)]
+        pub set: Option<String>,
+        #[serde(default)]
+        pub cn: Option<String>,
+        #[serde(default)]
+        pub lang: Option<String>,
+        #[serde(default)]
+        pub layout: Option<String>,
+        #[serde(default)]
+        pub type_line: Option<String>,
+    }
+
+    impl MoxfieldCardInfo {
+        fn card(&self, quantity: u32, finish: Option<Finish>, section: Option<&str>) -> Card {
+            Card {
+                name: self.name.clone(),
+                scryfall_id: self.scryfall_id.clone(),
+                quantity,
+                lang: self.lang.clone().filter(|lang| lang != "en"),
+                set: self.set.clone(),
+                collector_number: self.cn.clone(),
+                finish,
+                layout: self.layout.clone(),
+                type_line: self.type_line.clone(),
+                section: section.map(str::to_string),
+                ..Card::default()
+            }
+        }
     }
 
     #[derive(Debug, Deserialize)]
     struct MoxfieldResponse {
         pub name: String,
+        /// The card the deck is shown with, usually its commander.
         pub main: MoxfieldCardInfo,
+        #[serde(default)]
+        pub commanders: HashMap<String, MoxfieldCard>,
+        #[serde(default)]
+        pub companions: HashMap<String, MoxfieldCard>,
         pub mainboard: HashMap<String, MoxfieldCard>,
+        #[serde(default)]
+        pub sideboard: HashMap<String, MoxfieldCard>,
+        #[serde(default)]
+        pub maybeboard: HashMap<String, MoxfieldCard>,
         pub tokens: Vec<MoxfieldCardInfo>,
     }
 
-    let deck_id = url.path_segments().unwrap().nth(1).unwrap();
+    // e.g. https://moxfield.com/decks/<id>
+    let deck_id = url
+        .path_segments()
+        .and_then(|mut segments| segments.nth(1))
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| Error::parse(&url, "no deck id in URL"))?;
     let deck_url = format!("{}/{}", MOXFIELD_API_URL, deck_id);
-    let response = reqwest::get(deck_url).await?;
+    let response = credentials
+        .client()?
+        .get(&deck_url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::network(&deck_url, e))?;
 
     // pretty-print json for easier debugging
-    let data = serde_json::to_string_pretty(&response.json::<serde_json::Value>().await?)?;
+    let data = response
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| Error::network(&deck_url, e))?;
+    let data = serde_json::to_string_pretty(&data).map_err(|e| Error::parse(&deck_url, e))?;
 
-    let response: MoxfieldResponse = serde_json::from_str(&data)?;
+    let response: MoxfieldResponse =
+        serde_json::from_str(&data).map_err(|e| Error::parse(&deck_url, e))?;
 
     let mut cards = Vec::new();
+    // Older decks only name their commander as the main card
+    if response.commanders.is_empty() {
+        cards.push(response.main.card(1, None, None));
+    }
 
-    cards.push(Card {
-        name: response.main.name,
-        scryfall_id: response.main.scryfall_id,
-        backface: false,
-    });
-    for card in response.mainboard.values() {
-        for _ in 0..card.quantity {
-            cards.push(Card {
+    let boards = [
+        (&response.commanders, Some("commander")),
+        (&response.companions, Some("companion")),
+        (&response.mainboard, None),
+        (&response.sideboard, Some("sideboard")),
+        (&response.maybeboard, Some("maybeboard")),
+    ];
+    for (board, section) in boards {
+        for card in board.values() {
+            reporter.event(&Event::CardResolved {
                 name: card.card.name.clone(),
                 scryfall_id: card.card.scryfall_id.clone(),
-                backface: false,
+                quantity: card.quantity,
             });
+            cards.push(card.card.card(card.quantity, card.finish, section));
         }
     }
 
-    let mut tokens = Vec::new();
-    for card in response.tokens {
-        tokens.push(Card {
-            name: card.name.clone(),
-            scryfall_id: card.scryfall_id.clone(),
-            backface: false,
-        });
-    }
+    let tokens = response
+        .tokens
+        .iter()
+        .map(|token| token.card(1, None, None))
+        .collect();
 
-    Ok(Deck {
-        name: response.name,
-        cards,
-        tokens,
-    })
+    Ok(Deck::new(response.name, cards, tokens))
 }
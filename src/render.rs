@@ -0,0 +1,379 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+
+use printpdf::path::{PaintMode, WindingOrder};
+use printpdf::*;
+
+use crate::deck::{CardImages, Deck, DeckGenerationOptions, PaperSize, Separator};
+use crate::error::{Error, Failure, FailureStage, Result};
+use crate::events::Event;
+use crate::progress::{Progress, Reporter};
+
+const PAGE_MARGIN: Mm = Mm(15.0);
+const CARD_WIDTH: Mm = Mm(63.0);
+const CARD_HEIGHT: Mm = Mm(87.8);
+const IMAGE_DPI: f32 = 1200.0;
+/// Scale that prints a large (672 pixels wide) image at card size.
+const LARGE_IMAGE_SCALE: f32 = 4.42;
+const LARGE_IMAGE_WIDTH: f32 = 672.0;
+/// Characters of a placeholder label that fit across a card at 11pt.
+const PLACEHOLDER_LINE_LENGTH: usize = 26;
+
+impl PaperSize {
+    fn dimensions(&self) -> (Mm, Mm) {
+        match self {
+            PaperSize::A4 => (Mm(210.0), Mm(297.0)),
+            PaperSize::A3 => (Mm(297.0), Mm(420.0)),
+            PaperSize::Letter => (Mm(215.9), Mm(279.4)),
+            PaperSize::Legal => (Mm(215.9), Mm(355.6)),
+        }
+    }
+}
+
+impl Deck {
+    /// Downloads the images and writes the PDF, returning the cards that were
+    /// left out in tolerant mode.
+    pub async fn generate(&self, options: DeckGenerationOptions) -> Result<Vec<Failure>> {
+        let reporter = &options.reporter;
+        reporter.status(&format!(
+            "Generating deck: {}",
+            &options
+                .filename
+                .clone()
+                .unwrap_or_else(|| self.name.clone())
+        ));
+        match options.print_tokens {
+            true => reporter.status(&format!(
+                "Total cards: {} ({} mainboard, {} tokens)",
+                self.total_cards(),
+                self.card_count(),
+                self.token_count()
+            )),
+            false => {
+                reporter.status("Skipping tokens...");
+                reporter.status(&format!(
+                    "Total cards: {} ({} mainboard)",
+                    self.total_cards(),
+                    self.card_count()
+                ))
+            }
+        }
+
+        let images = self.fetch_images(options.clone()).await?;
+        let mut failures = self.failures.clone();
+        failures.extend(images.failures.iter().cloned());
+
+        let path = self.pdf_filename(&options);
+        let file = File::create(&path).map_err(|e| Error::PdfWrite {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        let pages = self.render(&images, &options, file).map_err(|e| match e {
+            Error::PdfRender(message) => Error::PdfWrite {
+                path: path.clone(),
+                message,
+            },
+            e => e,
+        })?;
+
+        reporter.event(&Event::Finished {
+            path: Some(path),
+            cards: self.card_count(),
+            tokens: match options.print_tokens {
+                true => self.token_count(),
+                false => 0,
+            },
+            pages,
+            failed: failures
+                .iter()
+                .map(|failure| failure.quantity as usize)
+                .sum(),
+        });
+
+        Ok(failures)
+    }
+
+    /// Where `generate` writes the PDF.
+    pub fn pdf_filename(&self, options: &DeckGenerationOptions) -> String {
+        options
+            .filename
+            .clone()
+            .unwrap_or_else(|| format!("{}.pdf", self.name))
+    }
+
+    /// Lays out the cards and writes the PDF to `writer`, returning the
+    /// number of pages. Cards without an image fail the render, except in
+    /// tolerant mode where they are left out or replaced by placeholders.
+    pub fn render(
+        &self,
+        images: &CardImages,
+        options: &DeckGenerationOptions,
+        writer: impl Write,
+    ) -> Result<usize> {
+        let (page_width, page_height) = options.paper.dimensions();
+        let (doc, page_idx, layer_idx) = PdfDocument::new("Deck", page_width, page_height, "Layer");
+        let pdf_error = |e: &dyn std::fmt::Display| Error::PdfRender(e.to_string());
+
+        let font = doc
+            .add_builtin_font(BuiltinFont::HelveticaBold)
+            .map_err(|e| pdf_error(&e))?;
+
+        let mut sheet = Sheet::new(
+            &doc,
+            doc.get_page(page_idx).get_layer(layer_idx),
+            options.paper,
+            &options.reporter,
+        );
+
+        // reference size box for card
+        let points = vec![
+            (Point::new(Mm(0.0), Mm(0.0)), false),
+            (Point::new(CARD_WIDTH, Mm(0.0)), false),
+            (Point::new(CARD_WIDTH, CARD_HEIGHT), false),
+            (Point::new(Mm(0.0), CARD_HEIGHT), false),
+        ];
+        let _line = Polygon {
+            rings: vec![points],
+            mode: PaintMode::FillStroke,
+            winding_order: WindingOrder::NonZero,
+        };
+
+        // layer.add_polygon(line);
+
+        let cards = self.print_order(options.print_tokens);
+
+        let progress = Progress::new(&options.reporter, "render", cards.len() as u64);
+        options.reporter.status("Rendering cards...");
+
+        let mut current_part = None;
+        for card in cards {
+            if self.parts.len() > 1 && current_part != Some(card.part) {
+                current_part = Some(card.part);
+                let title = &self.parts[card.part];
+                match options.separator {
+                    Separator::None => {}
+                    Separator::Page => {
+                        sheet.new_page();
+                        sheet.layer.use_text(
+                            title.as_str(),
+                            24.0,
+                            PAGE_MARGIN,
+                            page_height / 2.0,
+                            &font,
+                        );
+                        sheet.empty = false;
+                        sheet.new_page();
+                    }
+                    Separator::Header => {
+                        sheet.new_page();
+                        sheet.layer.use_text(
+                            title.as_str(),
+                            14.0,
+                            sheet.x,
+                            page_height - PAGE_MARGIN / 2.0,
+                            &font,
+                        );
+                        sheet.empty = false;
+                    }
+                }
+            }
+
+            log::debug!("Rendering {}", card.name);
+            let data = match images.get(&card) {
+                Some(data) => data,
+                // The download failed and has been reported already
+                None if options.tolerant => {
+                    if options.placeholders {
+                        let (x, y) = sheet.next_slot();
+                        draw_placeholder(&sheet.layer, &font, x, y, &card.name);
+                    }
+                    progress.inc(&card.name);
+                    continue;
+                }
+                None => {
+                    return Err(Error::ImageDecode {
+                        card: card.name.clone(),
+                        message: "no image fetched".to_string(),
+                    })
+                }
+            };
+            let decode_error = |message: String| Error::ImageDecode {
+                card: card.name.clone(),
+                message,
+            };
+            let decoder = image_crate::codecs::jpeg::JpegDecoder::new(Cursor::new(data))
+                .map_err(|e| decode_error(e.to_string()))?;
+            // Smaller image versions are scaled up to the same card size
+            let scale = LARGE_IMAGE_SCALE * LARGE_IMAGE_WIDTH
+                / image_crate::ImageDecoder::dimensions(&decoder).0 as f32;
+            let image = Image::try_from(decoder).map_err(|e| decode_error(e.to_string()))?;
+
+            let (x, y) = sheet.next_slot();
+
+            image.add_to_layer(
+                sheet.layer.clone(),
+                ImageTransform {
+                    translate_x: Some(x),
+                    translate_y: Some(y),
+                    dpi: Some(IMAGE_DPI),
+                    scale_x: Some(scale),
+                    scale_y: Some(scale),
+                    rotate: Some(ImageRotation::default()),
+                },
+            );
+
+            progress.inc(&card.name);
+        }
+
+        // Cards that never resolved have no place in the print order, they go last
+        if options.placeholders {
+            let unresolved = self
+                .failures
+                .iter()
+                .filter(|failure| failure.stage == FailureStage::Resolve);
+            for failure in unresolved {
+                for _ in 0..failure.quantity {
+                    let (x, y) = sheet.next_slot();
+                    draw_placeholder(&sheet.layer, &font, x, y, &failure.card);
+                }
+            }
+        }
+
+        progress.finish();
+
+        let pages = sheet.pages;
+        options.reporter.event(&Event::PageRendered { page: pages });
+
+        let mut writer = BufWriter::new(writer);
+        doc.save(&mut writer).map_err(|e| pdf_error(&e))?;
+        writer.flush().map_err(|e| pdf_error(&e))?;
+
+        Ok(pages)
+    }
+
+    /// Renders the PDF in memory, e.g. to upload it somewhere.
+    pub fn render_to_vec(
+        &self,
+        images: &CardImages,
+        options: &DeckGenerationOptions,
+    ) -> Result<Vec<u8>> {
+        let mut pdf = Vec::new();
+        self.render(images, options, &mut pdf)?;
+        Ok(pdf)
+    }
+}
+
+/// Outlines a card slot and writes what should have been printed there.
+fn draw_placeholder(layer: &PdfLayerReference, font: &IndirectFontRef, x: Mm, y: Mm, label: &str) {
+    let corner = |dx: Mm, dy: Mm| (Point::new(x + dx, y + dy), false);
+    layer.add_polygon(Polygon {
+        rings: vec![vec![
+            corner(Mm(0.0), Mm(0.0)),
+            corner(CARD_WIDTH, Mm(0.0)),
+            corner(CARD_WIDTH, CARD_HEIGHT),
+            corner(Mm(0.0), CARD_HEIGHT),
+        ]],
+        mode: PaintMode::Stroke,
+        winding_order: WindingOrder::NonZero,
+    });
+
+    // Wrap the label so it stays inside the outline
+    let mut lines: Vec<String> = Vec::new();
+    for word in label.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.len() + word.len() < PLACEHOLDER_LINE_LENGTH => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let top = y + CARD_HEIGHT - Mm(10.0) - Mm(5.0 * i as f32);
+        layer.use_text(line.as_str(), 11.0, x + Mm(4.0), top, font);
+    }
+}
+
+/// Tracks where the next card goes on the current page.
+struct Sheet<'a> {
+    doc: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    width: Mm,
+    height: Mm,
+    x: Mm,
+    y: Mm,
+    empty: bool,
+    /// Number of the current page, counting from 1.
+    pages: usize,
+    reporter: &'a Reporter,
+}
+
+impl<'a> Sheet<'a> {
+    fn new(
+        doc: &'a PdfDocumentReference,
+        layer: PdfLayerReference,
+        paper: PaperSize,
+        reporter: &'a Reporter,
+    ) -> Self {
+        let (width, height) = paper.dimensions();
+        let mut sheet = Sheet {
+            doc,
+            layer,
+            width,
+            height,
+            x: Mm(0.0),
+            y: Mm(0.0),
+            empty: true,
+            pages: 1,
+            reporter,
+        };
+        sheet.x = sheet.row_start();
+        sheet.y = sheet.first_row();
+        sheet
+    }
+
+    /// Left edge of a row of cards, centered on the page.
+    fn row_start(&self) -> Mm {
+        let cards_per_row = (self.width.0 / CARD_WIDTH.0).floor();
+        (self.width - Mm(cards_per_row * CARD_WIDTH.0)) / 2.0
+    }
+
+    fn first_row(&self) -> Mm {
+        self.height - CARD_HEIGHT - PAGE_MARGIN
+    }
+
+    /// Moves to a fresh page, unless nothing has been drawn on the current one yet.
+    fn new_page(&mut self) {
+        if self.empty {
+            return;
+        }
+
+        self.reporter
+            .event(&Event::PageRendered { page: self.pages });
+        self.pages += 1;
+
+        let (page_idx, layer_idx) = self.doc.add_page(self.width, self.height, "Layer 1");
+        self.layer = self.doc.get_page(page_idx).get_layer(layer_idx);
+        self.x = self.row_start();
+        self.y = self.first_row();
+        self.empty = true;
+    }
+
+    /// Returns the position of the next card, wrapping rows and pages as needed.
+    fn next_slot(&mut self) -> (Mm, Mm) {
+        if self.x + CARD_WIDTH > self.width {
+            self.x = self.row_start();
+            self.y -= CARD_HEIGHT;
+        }
+
+        if self.y < Mm(0.0) {
+            self.new_page();
+        }
+
+        let slot = (self.x, self.y);
+        self.x += CARD_WIDTH;
+        self.empty = false;
+        slot
+    }
+}
@@ -0,0 +1,359 @@
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    RETRY_AFTER,
+};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tokio::time::{sleep, Instant};
+
+use crate::error::{Error, Result};
+
+/// Scryfall asks for 50-100 milliseconds between requests.
+const REQUESTS_PER_SECOND: f64 = 10.0;
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+const MAX_RETRIES: u32 = 5;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// HTTP client for everything tiffer fetches from Scryfall. Card lookups and
+/// image downloads share its rate limit through `ScryfallClient::shared`.
+#[derive(Debug)]
+pub struct ScryfallClient {
+    client: reqwest::Client,
+    bucket: Mutex<TokenBucket>,
+    in_flight: Semaphore,
+}
+
+/// An image along with the validators needed to check later whether it changed.
+#[derive(Debug)]
+pub struct FetchedImage {
+    pub data: Vec<u8>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Spaces requests evenly at `rate` per second. It holds at most one token,
+/// so there are no bursts, not even right after starting.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Takes a token, or returns how long to wait until one is available.
+    fn take(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(1.0);
+        self.refilled = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None;
+        }
+
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+}
+
+impl ScryfallClient {
+    fn new(requests_per_second: f64, max_concurrent_requests: usize) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent("curl/7.68.0")
+            .build()
+            .map_err(|e| Error::network("https://api.scryfall.com", e))?;
+
+        Ok(ScryfallClient {
+            client,
+            bucket: Mutex::new(TokenBucket {
+                rate: requests_per_second,
+                tokens: 1.0,
+                refilled: Instant::now(),
+            }),
+            in_flight: Semaphore::new(max_concurrent_requests),
+        })
+    }
+
+    /// The client shared by the whole process.
+    pub fn shared() -> Result<&'static ScryfallClient> {
+        static SHARED: OnceLock<ScryfallClient> = OnceLock::new();
+        if let Some(client) = SHARED.get() {
+            return Ok(client);
+        }
+
+        let client = ScryfallClient::new(REQUESTS_PER_SECOND, MAX_CONCURRENT_REQUESTS)?;
+        // Another task may have won the race, either client will do
+        Ok(SHARED.get_or_init(|| client))
+    }
+
+    /// Fetches a JSON object, returning `None` if it doesn't exist.
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Option<T>> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+
+        // Reading the body counts towards the concurrent requests as well
+        let (resp, _permit) = self.get(url, query, headers).await?;
+
+        match resp.status() {
+            status if status.is_success() => {
+                let json = resp.json().await.map_err(|e| Error::network(url, e))?;
+                Ok(Some(json))
+            }
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(Error::network(url, status)),
+        }
+    }
+
+    /// Downloads an image, following Scryfall's redirect to the image host.
+    pub async fn get_image(&self, url: &str) -> Result<FetchedImage> {
+        self.get_image_if_modified(url, None, None)
+            .await?
+            .ok_or_else(|| Error::network(url, "unexpected 304"))
+    }
+
+    /// Downloads an image unless it still matches the given validators, in
+    /// which case `None` is returned.
+    pub async fn get_image_if_modified(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<Option<FetchedImage>> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("image/jpeg"));
+        if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = last_modified.and_then(|date| HeaderValue::from_str(date).ok()) {
+            headers.insert(IF_MODIFIED_SINCE, date);
+        }
+
+        let (resp, _permit) = self.get(url, &[], headers).await?;
+
+        match resp.status() {
+            StatusCode::NOT_MODIFIED => Ok(None),
+            status if status.is_success() => {
+                let header = |name| {
+                    resp.headers()
+                        .get(name)
+                        .and_then(|value: &HeaderValue| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                let etag = header(ETAG);
+                let last_modified = header(LAST_MODIFIED);
+
+                Ok(Some(FetchedImage {
+                    data: resp
+                        .bytes()
+                        .await
+                        .map_err(|e| Error::network(url, e))?
+                        .to_vec(),
+                    etag,
+                    last_modified,
+                }))
+            }
+            status => Err(Error::network(url, status)),
+        }
+    }
+
+    async fn wait_for_turn(&self) {
+        loop {
+            let wait = self.bucket.lock().await.take();
+            match wait {
+                Some(wait) => sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    /// Sends a GET request, retrying on rate limiting, server and connection
+    /// errors. Hold on to the permit until the body has been read.
+    async fn get(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+        headers: HeaderMap,
+    ) -> Result<(Response, SemaphorePermit<'_>)> {
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let permit = self
+                .in_flight
+                .acquire()
+                .await
+                .map_err(|e| Error::network(url, e))?;
+            self.wait_for_turn().await;
+            let result = self
+                .client
+                .get(url)
+                .query(query)
+                .headers(headers.clone())
+                .send()
+                .await;
+
+            match result {
+                Ok(resp)
+                    if resp.status() == StatusCode::TOO_MANY_REQUESTS
+                        || resp.status().is_server_error() =>
+                {
+                    if attempts >= MAX_RETRIES {
+                        return match resp.status() {
+                            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+                                url: url.to_string(),
+                                attempts,
+                            }),
+                            _ => Ok((resp, permit)),
+                        };
+                    }
+
+                    let delay = retry_delay(&resp, attempts);
+                    match resp.status() {
+                        StatusCode::TOO_MANY_REQUESTS => {
+                            log::warn!("Rate limit exceeded, retrying {} in {:?}...", url, delay)
+                        }
+                        status => log::warn!("{} from {}, retrying in {:?}...", status, url, delay),
+                    }
+                    // Let other requests go ahead while this one waits
+                    drop(permit);
+                    sleep(delay).await;
+                }
+                Ok(resp) => return Ok((resp, permit)),
+                Err(e) => {
+                    if attempts >= MAX_RETRIES {
+                        return Err(Error::network(
+                            url,
+                            format!("{} (after {} attempts)", e, MAX_RETRIES),
+                        ));
+                    }
+
+                    log::warn!("Error occurred while requesting {}: {}", url, e);
+                    drop(permit);
+                    sleep(with_jitter(backoff(attempts))).await;
+                }
+            }
+        }
+    }
+}
+
+/// How long to wait before retrying a failed response, honouring its
+/// Retry-After header when there is one.
+fn retry_delay(resp: &Response, attempts: u32) -> Duration {
+    let delay = resp
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(parse_retry_after_header)
+        .unwrap_or_else(|| backoff(attempts));
+
+    with_jitter(delay)
+}
+
+/// Exponential backoff for responses that don't say how long to wait.
+fn backoff(attempts: u32) -> Duration {
+    Duration::from_secs(2u64.pow(attempts))
+}
+
+/// Spreads out retries of concurrent requests so they don't all hit
+/// Scryfall at the same moment, never waiting longer than `MAX_RETRY_DELAY`.
+fn with_jitter(delay: Duration) -> Duration {
+    delay
+        .mul_f64(rand::thread_rng().gen_range(1.0..1.25))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Parses both forms of Retry-After: a number of seconds or an HTTP date.
+fn parse_retry_after_header(header: &HeaderValue) -> Option<Duration> {
+    let value = header.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    // A date in the past means we can retry right away
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(value: &str) -> HeaderValue {
+        HeaderValue::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        assert_eq!(
+            parse_retry_after_header(&header("120")),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after_header(&header(" 0 ")),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn retry_after_date() {
+        let in_a_minute = SystemTime::now() + Duration::from_secs(60);
+        let delay =
+            parse_retry_after_header(&header(&httpdate::fmt_http_date(in_a_minute))).unwrap();
+
+        // HTTP dates only have whole seconds
+        assert!(delay > Duration::from_secs(58) && delay <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn retry_after_date_in_the_past() {
+        assert_eq!(
+            parse_retry_after_header(&header("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn retry_after_invalid() {
+        assert_eq!(parse_retry_after_header(&header("soon")), None);
+        assert_eq!(parse_retry_after_header(&header("-5")), None);
+    }
+
+    #[test]
+    fn bucket_spaces_requests_from_the_start() {
+        let mut bucket = TokenBucket {
+            rate: 10.0,
+            tokens: 1.0,
+            refilled: Instant::now(),
+        };
+
+        assert_eq!(bucket.take(), None);
+        let wait = bucket.take().unwrap();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn jitter_adds_up_to_a_quarter() {
+        let delay = Duration::from_secs(4);
+        for _ in 0..100 {
+            let jittered = with_jitter(delay);
+            assert!(jittered >= delay && jittered < delay.mul_f64(1.25));
+        }
+    }
+
+    #[test]
+    fn jitter_never_exceeds_max_delay() {
+        assert_eq!(with_jitter(MAX_RETRY_DELAY), MAX_RETRY_DELAY);
+        assert_eq!(with_jitter(Duration::from_secs(3600)), MAX_RETRY_DELAY);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::Path;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use crate::cache;
+use crate::deck::Deck;
+use crate::error::{Error, Result};
+
+/// File names ending in this are read as snapshots when given as a source.
+pub const SNAPSHOT_EXTENSION: &str = ".tiffer.json";
+
+/// Bumped whenever a snapshot written by an older tiffer can't be read as is.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// A fully resolved deck as saved to disk, printable again later with the
+/// exact same printings and without looking anything up.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot<D> {
+    version: u32,
+    deck: D,
+}
+
+impl Deck {
+    /// Saves the deck as a snapshot, see `SNAPSHOT_EXTENSION`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            deck: self,
+        };
+        let json =
+            serde_json::to_string_pretty(&snapshot).map_err(|e| Error::parse(path.display(), e))?;
+
+        cache::write_atomic(&path.to_string_lossy(), json.as_bytes())
+    }
+
+    /// Reads a deck saved by `Deck::save`.
+    pub fn load(path: &Path) -> Result<Deck> {
+        let json = fs::read_to_string(path).map_err(|e| Error::read(path.display(), e))?;
+
+        // Check the version first, the deck of another version may not parse
+        let snapshot: Snapshot<IgnoredAny> =
+            serde_json::from_str(&json).map_err(|e| Error::parse(path.display(), e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(Error::parse(
+                path.display(),
+                format!("unsupported snapshot version {}", snapshot.version),
+            ));
+        }
+
+        let snapshot: Snapshot<Deck> =
+            serde_json::from_str(&json).map_err(|e| Error::parse(path.display(), e))?;
+        Ok(snapshot.deck)
+    }
+}
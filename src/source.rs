@@ -1,12 +1,17 @@
+use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
 
 use url::Url;
 
+use crate::snapshot::SNAPSHOT_EXTENSION;
+
 #[derive(Debug, Clone)]
 pub enum Source {
     File(PathBuf),
     Link(Url),
+    /// A deck saved by `Deck::save`, recognized by `SNAPSHOT_EXTENSION`.
+    Snapshot(PathBuf),
 }
 
 impl FromStr for Source {
@@ -16,6 +21,9 @@ impl FromStr for Source {
         // Attempt to parse as a path
         let Ok(path) = PathBuf::from_str(s);
         if path.is_file() {
+            if s.ends_with(SNAPSHOT_EXTENSION) {
+                return Ok(Source::Snapshot(path));
+            }
             return Ok(Source::File(path));
         }
 
@@ -26,3 +34,12 @@ impl FromStr for Source {
         Err(format!("Invalid path or URL: {}", s))
     }
 }
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File(path) | Source::Snapshot(path) => write!(f, "{}", path.display()),
+            Source::Link(url) => write!(f, "{}", url),
+        }
+    }
+}
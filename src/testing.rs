@@ -0,0 +1,21 @@
+//! Fixtures shared by the unit tests.
+
+use crate::deck::Card;
+
+/// A copy of a card with nothing but its name and printing.
+pub(crate) fn card(name: &str, scryfall_id: &str, quantity: u32) -> Card {
+    Card {
+        name: name.to_string(),
+        scryfall_id: scryfall_id.to_string(),
+        quantity,
+        ..Card::default()
+    }
+}
+
+/// The back face of a double-faced `front`.
+pub(crate) fn back_of(front: &Card) -> Card {
+    Card {
+        backface: true,
+        ..front.clone()
+    }
+}